@@ -0,0 +1,304 @@
+use core::cell::{Cell, RefCell};
+
+use crate::{swap::Swap, Controlled, Curve, Signal};
+
+/// A sequence of timed target values for a parameter
+///
+/// Each breakpoint specifies a value to reach, the time at which it should be reached, and the
+/// shape of the transition from the previous breakpoint. The first transition begins from the
+/// parameter's value at the moment the automation is received by the audio thread, and the value
+/// of the last breakpoint is held once it's reached. Because automation is evaluated on the audio
+/// clock, fades and swells are sample-accurate regardless of how often the game updates.
+///
+/// Automation has a fixed capacity so that it can be sent to the audio thread without allocating,
+/// e.g. with [`GainControl::automate`](crate::GainControl::automate). Any other controllable
+/// parameter can be automated with an [`Automator`], and custom signals can use
+/// [`value`](Self::value) to evaluate automation sent through a [`Swap`](crate::Swap).
+///
+/// # Example
+/// ```
+/// # use oddio::{Automation, Curve};
+/// // Swell to 0 dB over a second, then fade out over two more
+/// let swell = Automation::new()
+///     .then(1.0, 0.0, Curve::SCurve)
+///     .then(3.0, -60.0, Curve::Linear);
+/// assert_eq!(swell.value(-20.0, 0.0), -20.0);
+/// assert_eq!(swell.value(-20.0, 2.0), -30.0);
+/// assert_eq!(swell.value(-20.0, 10.0), -60.0);
+/// ```
+#[derive(Debug, Copy, Clone)]
+pub struct Automation {
+    points: [Breakpoint; Automation::CAPACITY],
+    len: usize,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Breakpoint {
+    time: f32,
+    value: f32,
+    curve: Curve,
+}
+
+impl Automation {
+    /// Maximum number of breakpoints
+    pub const CAPACITY: usize = 16;
+
+    /// Automation with no breakpoints, which holds the parameter's current value
+    pub fn new() -> Self {
+        Self {
+            points: [Breakpoint {
+                time: 0.0,
+                value: 0.0,
+                curve: Curve::Linear,
+            }; Self::CAPACITY],
+            len: 0,
+        }
+    }
+
+    /// Add a breakpoint reaching `value` at `time` seconds after the automation begins, approached
+    /// along `curve`
+    ///
+    /// # Panics
+    ///
+    /// Panics if [`CAPACITY`](Self::CAPACITY) breakpoints have already been added, or if `time`
+    /// precedes that of the previous breakpoint.
+    pub fn then(mut self, time: f32, value: f32, curve: Curve) -> Self {
+        assert!(self.len < Self::CAPACITY, "too many breakpoints");
+        assert!(
+            time >= self.duration(),
+            "breakpoints must be added in order of time"
+        );
+        self.points[self.len] = Breakpoint { time, value, curve };
+        self.len += 1;
+        self
+    }
+
+    /// Number of breakpoints
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether there are no breakpoints
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Seconds from the beginning of the automation until the last breakpoint
+    pub fn duration(&self) -> f32 {
+        self.points[..self.len].last().map_or(0.0, |x| x.time)
+    }
+
+    /// Value `t` seconds after the automation begins, given that the parameter was `start`
+    /// when it began
+    pub fn value(&self, start: f32, t: f32) -> f32 {
+        let (mut prev_time, mut prev_value) = (0.0, start);
+        for point in &self.points[..self.len] {
+            if t < point.time {
+                let progress = (t - prev_time) / (point.time - prev_time);
+                return prev_value + (point.value - prev_value) * point.curve.apply(progress);
+            }
+            prev_time = point.time;
+            prev_value = point.value;
+        }
+        prev_value
+    }
+}
+
+impl Default for Automation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Automation in progress
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Automated {
+    automation: Automation,
+    start: f32,
+    /// Seconds since the automation began
+    elapsed: f32,
+}
+
+impl Automated {
+    pub(crate) fn new(automation: Automation, start: f32) -> Self {
+        Self {
+            automation,
+            start,
+            elapsed: 0.0,
+        }
+    }
+
+    /// Current value
+    pub(crate) fn get(&self) -> f32 {
+        self.automation.value(self.start, self.elapsed)
+    }
+
+    pub(crate) fn advance(&mut self, seconds: f32) {
+        self.elapsed += seconds;
+    }
+}
+
+/// Drives a parameter of a signal with [`Automation`], by passing the signal's control and the
+/// parameter's value to a function
+///
+/// Controls generally take effect once per call to [`Signal::sample`], so the inner signal is
+/// sampled in chunks of 16 frames while automation is in progress, and the value at the start of
+/// each chunk is applied. Because the signal is controlled from the audio thread, its control
+/// can't be obtained through a [`Handle`](crate::Handle) as well.
+///
+/// # Example
+/// ```
+/// # use oddio::*;
+/// // Sweep a low-pass filter's cutoff from 200 Hz to 5 kHz over two seconds
+/// let filter = Biquad::new(Constant::new(0.0), BiquadOptions::default());
+/// let (mut handle, _) = split(Automator::new(filter, 200.0, |filter, cutoff| {
+///     filter.set_options(BiquadOptions {
+///         frequency: cutoff,
+///         ..BiquadOptions::default()
+///     })
+/// }));
+/// handle
+///     .control::<Automator<_, _>, _>()
+///     .automate(Automation::new().then(2.0, 5000.0, Curve::Linear));
+/// ```
+pub struct Automator<T, F> {
+    automation: Swap<Option<Automation>>,
+    automated: RefCell<Option<Automated>>,
+    /// Value most recently applied
+    current: Cell<f32>,
+    apply: F,
+    inner: T,
+}
+
+impl<T, F> Automator<T, F>
+where
+    T: for<'a> Controlled<'a>,
+    F: for<'a> Fn(&mut <T as Controlled<'a>>::Control, f32),
+{
+    /// Automate a parameter of `signal` with `apply`, given that it's initially `value`
+    pub fn new(signal: T, value: f32, apply: F) -> Self {
+        Self {
+            automation: Swap::new(None),
+            automated: RefCell::new(None),
+            current: Cell::new(value),
+            apply,
+            inner: signal,
+        }
+    }
+}
+
+impl<T, F> Signal for Automator<T, F>
+where
+    T: Signal + for<'a> Controlled<'a>,
+    F: for<'a> Fn(&mut <T as Controlled<'a>>::Control, f32),
+{
+    type Frame = T::Frame;
+
+    #[allow(clippy::float_cmp)]
+    fn sample(&self, interval: f32, out: &mut [T::Frame]) {
+        let mut automated = self.automated.borrow_mut();
+        if self.automation.refresh() {
+            *automated = unsafe { *self.automation.received() }
+                .map(|x| Automated::new(x, self.current.get()));
+        }
+        let automated = match *automated {
+            Some(ref mut x) => x,
+            None => {
+                self.inner.sample(interval, out);
+                return;
+            }
+        };
+        for chunk in out.chunks_mut(AUTOMATION_CHUNK) {
+            let value = automated.get();
+            if value != self.current.get() {
+                // Sound because no other control for `inner` can exist
+                let mut control = unsafe { T::make_control(&self.inner) };
+                (self.apply)(&mut control, value);
+                self.current.set(value);
+            }
+            self.inner.sample(interval, chunk);
+            automated.advance(interval * chunk.len() as f32);
+        }
+    }
+
+    fn remaining(&self) -> f32 {
+        self.inner.remaining()
+    }
+
+    #[inline]
+    fn handle_dropped(&self) {
+        self.inner.handle_dropped();
+    }
+}
+
+/// Number of frames sampled at a time while automation is in progress
+const AUTOMATION_CHUNK: usize = 16;
+
+/// Thread-safe control for an [`Automator`]
+pub struct AutomatorControl<'a>(&'a Swap<Option<Automation>>);
+
+unsafe impl<'a, T: 'a, F: 'a> Controlled<'a> for Automator<T, F> {
+    type Control = AutomatorControl<'a>;
+
+    unsafe fn make_control(signal: &'a Automator<T, F>) -> Self::Control {
+        AutomatorControl(&signal.automation)
+    }
+}
+
+impl<'a> AutomatorControl<'a> {
+    /// Drive the parameter with `automation`, replacing any automation in progress
+    ///
+    /// The parameter holds the final value of the automation until it's changed again.
+    pub fn automate(&mut self, automation: Automation) {
+        unsafe {
+            *self.0.pending() = Some(automation);
+        }
+        self.0.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Constant, Gain};
+
+    #[test]
+    fn value() {
+        let ramp = Automation::new()
+            .then(1.0, 10.0, Curve::Linear)
+            .then(1.0, 20.0, Curve::Linear)
+            .then(3.0, 0.0, Curve::Linear);
+        assert_eq!(ramp.duration(), 3.0);
+        assert_eq!(ramp.value(-10.0, 0.0), -10.0);
+        assert_eq!(ramp.value(-10.0, 0.5), 0.0);
+        // Breakpoints at the same time jump
+        assert_eq!(ramp.value(-10.0, 1.0), 20.0);
+        assert_eq!(ramp.value(-10.0, 2.0), 10.0);
+        assert_eq!(ramp.value(-10.0, 3.0), 0.0);
+        assert_eq!(ramp.value(-10.0, 100.0), 0.0);
+
+        // A breakpoint at time 0 is reached immediately
+        let jump = Automation::new().then(0.0, 1.0, Curve::Linear);
+        assert_eq!(jump.value(0.0, 0.0), 1.0);
+        assert_eq!(Automation::new().value(5.0, 1.0), 5.0);
+    }
+
+    #[test]
+    fn automator() {
+        let s = Automator::new(Gain::new(Constant(1.0)), 1.0, |gain, ratio| {
+            gain.set_amplitude_ratio(ratio)
+        });
+        let mut control = unsafe { Automator::make_control(&s) };
+        control.automate(Automation::new().then(0.0, 0.5, Curve::Linear));
+        let mut buf = [0.0; 32];
+        // Long enough for the gain's smoothing to complete
+        s.sample(0.1, &mut buf);
+        assert_eq!(s.current.get(), 0.5);
+        assert_eq!(buf[31], 0.5);
+
+        control.automate(Automation::new().then(3.2, 2.1, Curve::Linear));
+        s.sample(0.1, &mut buf);
+        // Applied at the start of each chunk
+        assert!((s.current.get() - 1.3).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,453 @@
+use alloc::boxed::Box;
+use core::{
+    cell::{Cell, RefCell},
+    f32::consts::{PI, TAU},
+};
+
+use crate::{math::Float, swap::Swap, Controlled, Filter, Frame, Signal, Smoothed};
+
+/// Shapes the frequency content of a signal with a second-order IIR filter
+///
+/// Covers the common equalizer building blocks: low-pass, high-pass, band-pass, notch, shelving,
+/// and peaking filters, using the well-known formulas from Robert Bristow-Johnson's "Audio EQ
+/// Cookbook". Changes to frequency, Q, and gain are smoothed, with coefficients recomputed every
+/// few samples on the audio thread, so they can be swept freely without zipper noise. Changes of
+/// [`BiquadKind`] take effect immediately, and may click.
+pub struct Biquad<T: ?Sized> {
+    shared: SharedOptions,
+    smoother: RefCell<Smoother>,
+    state: RefCell<Box<[[f32; 2]]>>,
+    inner: T,
+}
+
+impl<T: Signal> Biquad<T>
+where
+    T::Frame: Frame,
+{
+    /// Apply the filter described by `options` to `signal`
+    pub fn new(signal: T, options: BiquadOptions) -> Self {
+        Self {
+            shared: SharedOptions::new(options),
+            smoother: RefCell::new(Smoother::new(options)),
+            state: RefCell::new(state::<T::Frame>()),
+            inner: signal,
+        }
+    }
+}
+
+/// Configuration for a [`Biquad`] filter
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BiquadOptions {
+    /// Shape of the frequency response
+    pub kind: BiquadKind,
+    /// Cutoff, center, or corner frequency, in Hz
+    pub frequency: f32,
+    /// Quality factor, controlling the width of the affected band, or the resonance at the cutoff
+    /// for low- and high-pass filters. `FRAC_1_SQRT_2` gives the flattest passband.
+    pub q: f32,
+    /// Gain in decibels, used only by [`LowShelf`](BiquadKind::LowShelf),
+    /// [`HighShelf`](BiquadKind::HighShelf), and [`Peaking`](BiquadKind::Peaking) filters
+    pub gain: f32,
+}
+
+impl Default for BiquadOptions {
+    fn default() -> Self {
+        Self {
+            kind: BiquadKind::LowPass,
+            frequency: 1000.0,
+            q: core::f32::consts::FRAC_1_SQRT_2,
+            gain: 0.0,
+        }
+    }
+}
+
+/// Shape of the frequency response of a [`Biquad`] filter
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BiquadKind {
+    /// Attenuate frequencies above the cutoff
+    LowPass,
+    /// Attenuate frequencies below the cutoff
+    HighPass,
+    /// Pass only frequencies near the center, with a peak gain of 0 dB
+    BandPass,
+    /// Reject frequencies near the center
+    Notch,
+    /// Boost or cut frequencies below the corner
+    LowShelf,
+    /// Boost or cut frequencies above the corner
+    HighShelf,
+    /// Boost or cut frequencies near the center
+    Peaking,
+}
+
+/// Coefficients of a biquad filter, normalized so that `a0` is 1
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Coefficients {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Coefficients {
+    /// Coefficients implementing `options` at `sample_rate` Hz
+    pub(crate) fn new(options: &BiquadOptions, sample_rate: f32) -> Self {
+        let frequency = options.frequency.min(0.49 * sample_rate).max(1e-3);
+        let w0 = TAU * frequency / sample_rate;
+        let (sin, cos) = (w0.sin(), w0.cos());
+        let alpha = sin / (2.0 * options.q.max(1e-3));
+        let a = 10.0f32.powf(options.gain / 40.0);
+        let (b0, b1, b2, a0, a1, a2) = match options.kind {
+            BiquadKind::LowPass => (
+                (1.0 - cos) / 2.0,
+                1.0 - cos,
+                (1.0 - cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            BiquadKind::HighPass => (
+                (1.0 + cos) / 2.0,
+                -(1.0 + cos),
+                (1.0 + cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            BiquadKind::BandPass => (alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            BiquadKind::Notch => (1.0, -2.0 * cos, 1.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            BiquadKind::LowShelf => {
+                let k = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) - (a - 1.0) * cos + k),
+                    2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                    a * ((a + 1.0) - (a - 1.0) * cos - k),
+                    (a + 1.0) + (a - 1.0) * cos + k,
+                    -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                    (a + 1.0) + (a - 1.0) * cos - k,
+                )
+            }
+            BiquadKind::HighShelf => {
+                let k = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) + (a - 1.0) * cos + k),
+                    -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                    a * ((a + 1.0) + (a - 1.0) * cos - k),
+                    (a + 1.0) - (a - 1.0) * cos + k,
+                    2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                    (a + 1.0) - (a - 1.0) * cos - k,
+                )
+            }
+            BiquadKind::Peaking => (
+                1.0 + alpha * a,
+                -2.0 * cos,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ),
+        };
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+
+    /// Amplitude ratio applied to a sinusoid at `frequency` Hz, given `sample_rate` Hz
+    pub(crate) fn magnitude(&self, frequency: f32, sample_rate: f32) -> f32 {
+        let w = PI * (2.0 * frequency / sample_rate).min(1.0);
+        let (c1, s1) = (w.cos(), w.sin());
+        let (c2, s2) = ((2.0 * w).cos(), (2.0 * w).sin());
+        let num = (
+            self.b0 + self.b1 * c1 + self.b2 * c2,
+            self.b1 * s1 + self.b2 * s2,
+        );
+        let den = (
+            1.0 + self.a1 * c1 + self.a2 * c2,
+            self.a1 * s1 + self.a2 * s2,
+        );
+        ((num.0 * num.0 + num.1 * num.1) / (den.0 * den.0 + den.1 * den.1)).sqrt()
+    }
+
+    /// Filter one frame, using transposed direct form II
+    ///
+    /// `state` holds two delayed samples for each channel, as constructed by [`state`].
+    pub(crate) fn process<F: Frame>(&self, state: &mut [[f32; 2]], x: &F) -> F {
+        let mut y = F::ZERO;
+        for ((&x, y), [z1, z2]) in x.channels().iter().zip(y.channels_mut()).zip(state) {
+            *y = self.b0 * x + *z1;
+            *z1 = self.b1 * x - self.a1 * *y + *z2;
+            *z2 = self.b2 * x - self.a2 * *y;
+        }
+        y
+    }
+}
+
+/// Initial filter state for frames of type `F`
+pub(crate) fn state<F: Frame>() -> Box<[[f32; 2]]> {
+    F::ZERO.channels().iter().map(|_| [0.0; 2]).collect()
+}
+
+/// Filter configuration that can be updated from another thread
+pub(crate) struct SharedOptions {
+    /// Configuration most recently set. Control only.
+    latest: Cell<BiquadOptions>,
+    swap: Swap<BiquadOptions>,
+}
+
+impl SharedOptions {
+    pub(crate) fn new(options: BiquadOptions) -> Self {
+        Self {
+            latest: Cell::new(options),
+            swap: Swap::new(options),
+        }
+    }
+
+    /// Get the configuration most recently set. Control only.
+    pub(crate) fn load(&self) -> BiquadOptions {
+        self.latest.get()
+    }
+
+    /// Replace the configuration. Control only.
+    pub(crate) fn store(&self, options: BiquadOptions) {
+        self.latest.set(options);
+        unsafe {
+            *self.swap.pending() = options;
+        }
+        self.swap.flush();
+    }
+
+    /// Change part of the configuration. Control only.
+    pub(crate) fn update(&self, f: impl FnOnce(&mut BiquadOptions)) {
+        let mut options = self.load();
+        f(&mut options);
+        self.store(options);
+    }
+
+    /// Get the configuration most recently received. Signal only.
+    pub(crate) fn received(&self) -> BiquadOptions {
+        self.swap.refresh();
+        unsafe { *self.swap.received() }
+    }
+}
+
+/// Audio-thread state that glides filter parameters towards their targets
+pub(crate) struct Smoother {
+    kind: BiquadKind,
+    /// Base 2 logarithm of the frequency, so that sweeps are perceptually even
+    log_frequency: Smoothed<f32>,
+    q: Smoothed<f32>,
+    gain: Smoothed<f32>,
+    coefficients: Coefficients,
+    /// Sample interval for which `coefficients` were computed, or 0 if they're stale
+    interval: f32,
+}
+
+impl Smoother {
+    pub(crate) fn new(options: BiquadOptions) -> Self {
+        Self {
+            kind: options.kind,
+            log_frequency: Smoothed::new(options.frequency.max(1.0).log2()),
+            q: Smoothed::new(options.q),
+            gain: Smoothed::new(options.gain),
+            coefficients: Coefficients::new(&options, 1.0),
+            interval: 0.0,
+        }
+    }
+
+    /// Begin moving towards `target`
+    #[allow(clippy::float_cmp)]
+    pub(crate) fn set(&mut self, target: BiquadOptions) {
+        if self.kind != target.kind {
+            self.kind = target.kind;
+            self.interval = 0.0;
+        }
+        let log_frequency = target.frequency.max(1.0).log2();
+        for (smoothed, target) in [
+            (&mut self.log_frequency, log_frequency),
+            (&mut self.q, target.q),
+            (&mut self.gain, target.gain),
+        ] {
+            if smoothed.get() != target {
+                smoothed.set(target);
+            }
+        }
+    }
+
+    /// Parameters as of the current point in smoothing
+    pub(crate) fn current(&self) -> BiquadOptions {
+        BiquadOptions {
+            kind: self.kind,
+            frequency: 2.0f32.powf(self.log_frequency.get()),
+            q: self.q.get(),
+            gain: self.gain.get(),
+        }
+    }
+
+    /// Coefficients for the current parameters at sample `interval`
+    #[allow(clippy::float_cmp)]
+    pub(crate) fn coefficients(&mut self, interval: f32) -> Coefficients {
+        if self.interval != interval {
+            self.coefficients = Coefficients::new(&self.current(), 1.0 / interval);
+            self.interval = interval;
+        }
+        self.coefficients
+    }
+
+    /// Advance smoothing by `seconds`
+    pub(crate) fn advance(&mut self, seconds: f32) {
+        let smoothing = [&self.log_frequency, &self.q, &self.gain]
+            .iter()
+            .any(|x| x.progress() < 1.0);
+        if !smoothing {
+            return;
+        }
+        for x in [&mut self.log_frequency, &mut self.q, &mut self.gain] {
+            x.advance(seconds / SMOOTHING_PERIOD);
+        }
+        // Recompute on next use
+        self.interval = 0.0;
+    }
+}
+
+impl<T: Signal + ?Sized> Signal for Biquad<T>
+where
+    T::Frame: Frame,
+{
+    type Frame = T::Frame;
+
+    fn sample(&self, interval: f32, out: &mut [T::Frame]) {
+        self.inner.sample(interval, out);
+        let mut smoother = self.smoother.borrow_mut();
+        smoother.set(self.shared.received());
+        let state = &mut *self.state.borrow_mut();
+        for chunk in out.chunks_mut(CHUNK_SIZE) {
+            let coefficients = smoother.coefficients(interval);
+            for x in chunk.iter_mut() {
+                *x = coefficients.process(state, x);
+            }
+            smoother.advance(interval * chunk.len() as f32);
+        }
+    }
+
+    fn remaining(&self) -> f32 {
+        self.inner.remaining()
+    }
+
+    #[inline]
+    fn handle_dropped(&self) {
+        self.inner.handle_dropped();
+    }
+}
+
+impl<T: ?Sized> Filter for Biquad<T> {
+    type Inner = T;
+    fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+/// Thread-safe control for a [`Biquad`] filter
+pub struct BiquadControl<'a>(&'a SharedOptions);
+
+unsafe impl<'a, T: 'a> Controlled<'a> for Biquad<T> {
+    type Control = BiquadControl<'a>;
+
+    unsafe fn make_control(signal: &'a Biquad<T>) -> Self::Control {
+        BiquadControl(&signal.shared)
+    }
+}
+
+impl<'a> BiquadControl<'a> {
+    /// Get the configuration most recently set
+    pub fn options(&self) -> BiquadOptions {
+        self.0.load()
+    }
+
+    /// Replace the whole configuration
+    pub fn set_options(&mut self, options: BiquadOptions) {
+        self.0.store(options);
+    }
+
+    /// Change the shape of the frequency response
+    pub fn set_kind(&mut self, kind: BiquadKind) {
+        self.0.update(|x| x.kind = kind);
+    }
+
+    /// Set the cutoff, center, or corner frequency, in Hz
+    pub fn set_frequency(&mut self, frequency_hz: f32) {
+        self.0.update(|x| x.frequency = frequency_hz);
+    }
+
+    /// Set the quality factor
+    pub fn set_q(&mut self, q: f32) {
+        self.0.update(|x| x.q = q);
+    }
+
+    /// Set the gain of shelving and peaking filters, in decibels
+    pub fn set_gain(&mut self, db: f32) {
+        self.0.update(|x| x.gain = db);
+    }
+
+    /// Compute the amplitude ratio that the most recently set configuration applies to a sinusoid
+    /// at `frequency_hz`, when sampled at `sample_rate` Hz
+    pub fn magnitude(&self, frequency_hz: f32, sample_rate: u32) -> f32 {
+        let sample_rate = sample_rate as f32;
+        Coefficients::new(&self.0.load(), sample_rate).magnitude(frequency_hz, sample_rate)
+    }
+}
+
+/// Number of samples filtered between recomputations of coefficients while parameters change
+pub(crate) const CHUNK_SIZE: usize = 16;
+
+/// Number of seconds over which to smooth a change in parameters
+pub(crate) const SMOOTHING_PERIOD: f32 = 0.05;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Sine;
+
+    #[test]
+    fn response() {
+        let rate = 48_000.0;
+        let get = |kind, gain| {
+            let options = BiquadOptions {
+                kind,
+                gain,
+                ..BiquadOptions::default()
+            };
+            let c = Coefficients::new(&options, rate);
+            [20.0, 1000.0, 20_000.0].map(|f| c.magnitude(f, rate))
+        };
+        let close = |x: f32, y: f32| (x - y).abs() < 1e-2;
+
+        let lp = get(BiquadKind::LowPass, 0.0);
+        assert!(close(lp[0], 1.0) && close(lp[1], 0.5f32.sqrt()) && lp[2] < 0.01);
+        let hp = get(BiquadKind::HighPass, 0.0);
+        assert!(hp[0] < 0.01 && close(hp[1], 0.5f32.sqrt()) && close(hp[2], 1.0));
+        let notch = get(BiquadKind::Notch, 0.0);
+        assert!(close(notch[0], 1.0) && notch[1] < 1e-3);
+        let peak = get(BiquadKind::Peaking, 6.0);
+        assert!(close(peak[0], 1.0) && close(peak[1], 10.0f32.powf(6.0 / 20.0)));
+        let shelf = get(BiquadKind::LowShelf, -12.0);
+        assert!(close(shelf[0], 10.0f32.powf(-12.0 / 20.0)) && close(shelf[2], 1.0));
+    }
+
+    #[test]
+    fn low_pass() {
+        let peak = |frequency_hz: f32| {
+            let filter = Biquad::new(Sine::new(0.0, frequency_hz), BiquadOptions::default());
+            let mut buf = [0.0; 4800];
+            filter.sample(1.0 / 48_000.0, &mut buf);
+            // Skip the initial transient
+            buf[2400..].iter().fold(0.0f32, |acc, x| acc.max(x.abs()))
+        };
+        assert!((peak(100.0) - 1.0).abs() < 1e-2);
+        assert!(peak(10_000.0) < 0.02);
+    }
+}
@@ -0,0 +1,380 @@
+use alloc::{boxed::Box, string::String, sync::Arc, vec, vec::Vec};
+use core::{
+    cell::{Cell, RefCell, UnsafeCell},
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use crate::{
+    swap::Swap, Automation, Controlled, Curve, Frame, Gain, Handle, Mixer, MixerControl, Signal,
+    Smoothed, Stop,
+};
+
+/// A [`Signal`] that sums a fixed number of [`Mixer`] buses, whose gains and effect parameters can
+/// be driven by [`Snapshot`]s
+///
+/// Each bus is a [`Mixer`] played through a [`Gain`] into a master [`Mixer`]. Gains are
+/// interpolated in decibels, so transitions between snapshots sound perceptually linear.
+///
+/// Effect parameters are plain numbers, interpolated linearly, that can be applied to any effect by
+/// wrapping it in a [`Driven`] with the [`Param`] from [`BusesControl::param`].
+pub struct Buses<T> {
+    /// Control-side record of the mix
+    state: RefCell<State>,
+    /// Handle to each bus, only accessed through the control
+    buses: UnsafeCell<Box<[Handle<Bus<T>>]>>,
+    target: Swap<Target>,
+    recv: RefCell<Inner>,
+    /// Current value of each parameter, for [`Param`]s
+    params: Arc<[AtomicU32]>,
+    master: Mixer<T>,
+}
+
+impl<T> Buses<T>
+where
+    T: Frame + Copy + Send + 'static,
+{
+    /// Construct `count` buses, each with a gain of 0 dB, and no effect parameters
+    pub fn new(count: usize) -> Self {
+        Self::with_params(count, 0)
+    }
+
+    /// Construct `count` buses, each with a gain of 0 dB, and `params` effect parameters, each
+    /// initially 0
+    pub fn with_params(count: usize, params: usize) -> Self {
+        let master = Mixer::new();
+        let buses = {
+            // Sound because the master mixer's control is never used again
+            let mut control = unsafe { Mixer::make_control(&master) };
+            (0..count)
+                .map(|_| control.play(Gain::new(Mixer::new())))
+                .collect()
+        };
+        Self {
+            state: RefCell::new(State {
+                gains: vec![0.0; count].into(),
+                params: vec![0.0; params].into(),
+                active: Vec::new(),
+            }),
+            buses: UnsafeCell::new(buses),
+            target: Swap::new(Target {
+                params: vec![0.0; params].into(),
+                blend: 0.0,
+            }),
+            recv: RefCell::new(Inner {
+                params: vec![Smoothed::new(0.0); params].into(),
+                blend: 0.0,
+            }),
+            params: (0..params)
+                .map(|_| AtomicU32::new(0.0f32.to_bits()))
+                .collect(),
+            master,
+        }
+    }
+}
+
+/// A named set of bus gain and effect parameter adjustments, applied with
+/// [`BusesControl::activate`]
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    /// Identifies the snapshot for [`BusesControl::deactivate`]
+    pub name: String,
+    /// Decibels added to the gain of each bus while the snapshot is active. Buses beyond the end
+    /// are unaffected.
+    pub gains: Vec<f32>,
+    /// Amount added to each effect parameter while the snapshot is active. Parameters beyond the
+    /// end are unaffected.
+    pub params: Vec<f32>,
+}
+
+/// A bus as played into the master mixer
+type Bus<T> = Stop<Gain<Mixer<T>>>;
+
+struct State {
+    /// Gain of each bus in the absence of snapshots, in decibels
+    gains: Box<[f32]>,
+    /// Value of each parameter in the absence of snapshots
+    params: Box<[f32]>,
+    active: Vec<Snapshot>,
+}
+
+#[derive(Clone)]
+struct Target {
+    /// Value of each parameter
+    params: Box<[f32]>,
+    /// Seconds over which to transition to `params`
+    blend: f32,
+}
+
+struct Inner {
+    params: Box<[Smoothed<f32>]>,
+    /// Seconds over which the current transition takes place
+    blend: f32,
+}
+
+impl<T: Frame> Signal for Buses<T> {
+    type Frame = T;
+
+    fn sample(&self, interval: f32, out: &mut [T]) {
+        let this = &mut *self.recv.borrow_mut();
+        if self.target.refresh() {
+            let target = unsafe { &*self.target.received() };
+            for (param, &value) in this.params.iter_mut().zip(target.params.iter()) {
+                param.set(value);
+            }
+            this.blend = target.blend;
+        }
+
+        // Effects read their parameters once per block, before they're sampled
+        for (param, shared) in this.params.iter_mut().zip(self.params.iter()) {
+            if this.blend > 0.0 {
+                shared.store(param.get().to_bits(), Ordering::Relaxed);
+                param.advance(interval * out.len() as f32 / this.blend);
+            } else {
+                // Jump straight to the target
+                param.advance(1.0);
+                shared.store(param.get().to_bits(), Ordering::Relaxed);
+            }
+        }
+
+        self.master.sample(interval, out);
+    }
+}
+
+/// Thread-safe control for [`Buses`]
+pub struct BusesControl<'a, T>(&'a Buses<T>);
+
+unsafe impl<'a, T: 'a> Controlled<'a> for Buses<T> {
+    type Control = BusesControl<'a, T>;
+
+    unsafe fn make_control(signal: &'a Buses<T>) -> Self::Control {
+        BusesControl(signal)
+    }
+}
+
+impl<'a, T> BusesControl<'a, T> {
+    /// Access the mixer for bus `index`, e.g. to play signals on it
+    pub fn bus(&mut self, index: usize) -> MixerControl<'_, T> {
+        self.buses()[index].control::<Mixer<T>, _>()
+    }
+
+    /// Get effect parameter `index`, to drive an effect with [`Driven`]
+    pub fn param(&self, index: usize) -> Param {
+        assert!(index < self.0.params.len(), "no such parameter");
+        Param {
+            values: self.0.params.clone(),
+            index,
+        }
+    }
+
+    /// Set the gain of bus `index` in the absence of snapshots, in decibels
+    ///
+    /// The change takes effect over `seconds`, along with any other pending transitions.
+    pub fn set_gain(&mut self, index: usize, db: f32, seconds: f32) {
+        self.0.state.borrow_mut().gains[index] = db;
+        self.flush(seconds);
+    }
+
+    /// Set effect parameter `index` in the absence of snapshots
+    ///
+    /// The change takes effect over `seconds`, along with any other pending transitions.
+    pub fn set_param(&mut self, index: usize, value: f32, seconds: f32) {
+        self.0.state.borrow_mut().params[index] = value;
+        self.flush(seconds);
+    }
+
+    /// Apply the adjustments in `snapshot`, transitioning over `seconds`
+    ///
+    /// Adjustments from all active snapshots are summed. Activating a snapshot with the same name
+    /// as one that's already active replaces it.
+    pub fn activate(&mut self, snapshot: Snapshot, seconds: f32) {
+        {
+            let mut state = self.0.state.borrow_mut();
+            state.active.retain(|x| x.name != snapshot.name);
+            state.active.push(snapshot);
+        }
+        self.flush(seconds);
+    }
+
+    /// Remove the adjustments of the snapshot named `name`, if active, transitioning over
+    /// `seconds`
+    pub fn deactivate(&mut self, name: &str, seconds: f32) {
+        self.0.state.borrow_mut().active.retain(|x| x.name != name);
+        self.flush(seconds);
+    }
+
+    /// Whether a snapshot named `name` is active
+    pub fn is_active(&self, name: &str) -> bool {
+        self.0.state.borrow().active.iter().any(|x| x.name == name)
+    }
+
+    fn buses(&mut self) -> &mut [Handle<Bus<T>>] {
+        // Sound because only the control accesses the handles, and it's borrowed mutably
+        unsafe { &mut *self.0.buses.get() }
+    }
+
+    /// Send the mix described by the control-side state to the audio thread
+    fn flush(&mut self, seconds: f32) {
+        let state = self.0.state.borrow();
+        let mut gains = state.gains.clone();
+        let target = unsafe { &mut *self.0.target.pending() };
+        target.params.copy_from_slice(&state.params);
+        for snapshot in &state.active {
+            for (gain, &offset) in gains.iter_mut().zip(&snapshot.gains) {
+                *gain += offset;
+            }
+            for (param, &offset) in target.params.iter_mut().zip(&snapshot.params) {
+                *param += offset;
+            }
+        }
+        // A breakpoint at time 0 is reached immediately, so an instant transition is a jump
+        let seconds = seconds.max(0.0);
+        target.blend = seconds;
+        self.0.target.flush();
+        drop(state);
+
+        for (bus, &db) in self.buses().iter_mut().zip(gains.iter()) {
+            bus.control::<Gain<_>, _>().automate(Automation::new().then(
+                seconds,
+                db,
+                Curve::Linear,
+            ));
+        }
+    }
+}
+
+/// The current value of one of a [`Buses`]' effect parameters, obtained from
+/// [`BusesControl::param`]
+#[derive(Clone)]
+pub struct Param {
+    values: Arc<[AtomicU32]>,
+    index: usize,
+}
+
+impl Param {
+    /// Get the value as of the most recent block
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.values[self.index].load(Ordering::Relaxed))
+    }
+}
+
+/// Drives an effect with a [`Param`], by passing the effect's control and the parameter's value to
+/// a function whenever the value changes
+///
+/// The parameter is read once per block, before the effect is sampled. Because the effect is
+/// controlled from the audio thread, its control can't be obtained through a [`Handle`] as well.
+/// To control signals inside the effect, e.g. to play sounds through it on a [`Mixer`], first
+/// [`split`](crate::split) them out.
+///
+/// # Example
+/// ```
+/// # use oddio::*;
+/// let buses = Buses::<f32>::with_params(1, 1);
+/// let mut control = unsafe { Buses::make_control(&buses) };
+/// let (_, music) = split(Mixer::new());
+/// // Parameter 0 is the music's gain, in decibels
+/// let param = control.param(0);
+/// control.bus(0).play(Driven::new(Gain::new(music), param, |gain, db| gain.set_gain(db)));
+/// ```
+pub struct Driven<T, F> {
+    param: Param,
+    /// Value most recently applied, or NaN if none
+    applied: Cell<f32>,
+    apply: F,
+    inner: T,
+}
+
+impl<T, F> Driven<T, F>
+where
+    T: for<'a> Controlled<'a>,
+    F: for<'a> Fn(&mut <T as Controlled<'a>>::Control, f32),
+{
+    /// Apply `param` to `signal` with `apply`
+    pub fn new(signal: T, param: Param, apply: F) -> Self {
+        Self {
+            param,
+            applied: Cell::new(f32::NAN),
+            apply,
+            inner: signal,
+        }
+    }
+}
+
+impl<T, F> Signal for Driven<T, F>
+where
+    T: Signal + for<'a> Controlled<'a>,
+    F: for<'a> Fn(&mut <T as Controlled<'a>>::Control, f32),
+{
+    type Frame = T::Frame;
+
+    #[allow(clippy::float_cmp)]
+    fn sample(&self, interval: f32, out: &mut [T::Frame]) {
+        let value = self.param.get();
+        if value != self.applied.get() {
+            // Sound because no other control for `inner` can exist
+            let mut control = unsafe { T::make_control(&self.inner) };
+            (self.apply)(&mut control, value);
+            self.applied.set(value);
+        }
+        self.inner.sample(interval, out);
+    }
+
+    fn remaining(&self) -> f32 {
+        self.inner.remaining()
+    }
+
+    #[inline]
+    fn handle_dropped(&self) {
+        self.inner.handle_dropped();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Constant;
+
+    #[test]
+    fn snapshot() {
+        let buses = Buses::with_params(2, 1);
+        let mut control = unsafe { Buses::make_control(&buses) };
+        control.bus(0).play(Constant::new(1.0));
+        let param = control.param(0);
+        control.bus(1).play(Driven::new(
+            Gain::new(Constant::new(1.0)),
+            param.clone(),
+            |gain, ratio| gain.set_amplitude_ratio(ratio),
+        ));
+        control.set_param(0, 1.0, 0.0);
+        let mut buf = [0.0; 4];
+        buses.sample(0.25, &mut buf);
+        assert_eq!(param.get(), 1.0);
+        assert_eq!(buf, [2.0; 4]);
+
+        control.activate(
+            Snapshot {
+                name: "quiet".into(),
+                gains: vec![-20.0],
+                params: vec![-0.5],
+            },
+            1.0,
+        );
+        assert!(control.is_active("quiet"));
+        buses.sample(0.25, &mut buf);
+        assert_eq!(param.get(), 1.0);
+        assert_eq!(buf[0], 2.0);
+        assert!(buf.windows(2).all(|w| w[1] < w[0]));
+        buses.sample(0.25, &mut buf);
+        assert_eq!(param.get(), 0.5);
+        // Bus 1's gain is only adjusted through the parameter
+        assert!((buf[3] - 0.6).abs() < 1e-6);
+
+        // Instant transitions take effect immediately
+        control.deactivate("quiet", 0.0);
+        assert!(!control.is_active("quiet"));
+        buses.sample(0.25, &mut buf);
+        assert_eq!(param.get(), 1.0);
+        // Bus 0 jumps, while the driven gain applies its own smoothing
+        assert_eq!(buf[0], 1.5);
+        assert_eq!(buf[3], 2.0);
+    }
+}
@@ -0,0 +1,297 @@
+use alloc::boxed::Box;
+use core::{
+    cell::RefCell,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use crate::{frame, math::Float, swap::Swap, Controlled, Filter, Frame, Signal, Smoothed};
+
+/// Reduces the dynamic range of a signal by attenuating it while it's loud
+///
+/// The peak level of each frame, in decibels, is compared to the threshold, and the excess is
+/// reduced according to the ratio, with a soft knee easing the transition. The gain reduction
+/// rises and falls according to the attack and release times, and makeup gain is applied
+/// afterwards to restore the overall level.
+///
+/// With lookahead, the signal is delayed so that gain reduction can respond to transients before
+/// they're heard. Lookahead is limited to [`MAX_LOOKAHEAD`](Self::MAX_LOOKAHEAD) at sample rates up
+/// to 192kHz. Changes to the lookahead are glided, like a [`Delay`](crate::Delay)'s time, so they
+/// cause a brief pitch shift rather than a click.
+pub struct Compressor<T: Signal + ?Sized> {
+    options: Swap<CompressorOptions>,
+    /// Most recent gain reduction in decibels, for reporting
+    reduction: AtomicU32,
+    state: RefCell<State<T::Frame>>,
+    inner: T,
+}
+
+struct State<F> {
+    /// Gain reduction in decibels
+    reduction: f32,
+    /// Lookahead in seconds
+    lookahead: Smoothed<f32>,
+    /// Ring buffer of recent input, for lookahead
+    delay: Box<[F]>,
+    /// Index in `delay` of the next frame to write
+    cursor: usize,
+}
+
+impl<T: Signal + ?Sized> Compressor<T> {
+    /// Maximum lookahead, in seconds
+    pub const MAX_LOOKAHEAD: f32 = 0.01;
+}
+
+impl<T: Signal> Compressor<T>
+where
+    T::Frame: Frame,
+{
+    /// Compress `signal`
+    pub fn new(signal: T, options: CompressorOptions) -> Self {
+        // Room for interpolating past the maximum lookahead
+        let capacity = (Self::MAX_LOOKAHEAD * MAX_SAMPLE_RATE).ceil() as usize + 2;
+        Self {
+            options: Swap::new(options),
+            reduction: AtomicU32::new(0.0f32.to_bits()),
+            state: RefCell::new(State {
+                reduction: 0.0,
+                lookahead: {
+                    // Begin steady rather than gliding
+                    let mut lookahead = Smoothed::new(options.lookahead);
+                    lookahead.advance(1.0);
+                    lookahead
+                },
+                delay: (0..capacity).map(|_| T::Frame::ZERO).collect(),
+                cursor: 0,
+            }),
+            inner: signal,
+        }
+    }
+}
+
+/// Configuration for a [`Compressor`] filter
+#[derive(Debug, Copy, Clone)]
+pub struct CompressorOptions {
+    /// Level, in decibels, above which gain reduction begins
+    pub threshold: f32,
+    /// Proportion by which the level above the threshold is reduced. For example, with a ratio of
+    /// 4, a signal 8 dB over the threshold is reduced by 6 dB.
+    pub ratio: f32,
+    /// Width, in decibels, of the region around the threshold over which the ratio is phased in
+    pub knee: f32,
+    /// Time constant, in seconds, with which gain reduction increases
+    pub attack: f32,
+    /// Time constant, in seconds, with which gain reduction recovers
+    pub release: f32,
+    /// Gain, in decibels, applied after compression
+    pub makeup: f32,
+    /// Seconds by which the signal is delayed relative to the detector
+    pub lookahead: f32,
+}
+
+impl Default for CompressorOptions {
+    fn default() -> Self {
+        Self {
+            threshold: -18.0,
+            ratio: 4.0,
+            knee: 6.0,
+            attack: 0.01,
+            release: 0.1,
+            makeup: 0.0,
+            lookahead: 0.0,
+        }
+    }
+}
+
+impl CompressorOptions {
+    /// Gain reduction, in decibels, called for by a signal at `level` decibels
+    fn reduction(&self, level: f32) -> f32 {
+        let slope = 1.0 - 1.0 / self.ratio;
+        let over = level - self.threshold;
+        if 2.0 * over <= -self.knee {
+            0.0
+        } else if 2.0 * over < self.knee {
+            let x = over + self.knee / 2.0;
+            slope * x * x / (2.0 * self.knee)
+        } else {
+            slope * over
+        }
+    }
+}
+
+impl<T: Signal + ?Sized> Signal for Compressor<T>
+where
+    T::Frame: Frame,
+{
+    type Frame = T::Frame;
+
+    fn sample(&self, interval: f32, out: &mut [T::Frame]) {
+        self.inner.sample(interval, out);
+        self.options.refresh();
+        let options = unsafe { *self.options.received() };
+        let attack = 1.0 - (-interval / options.attack).exp();
+        let release = 1.0 - (-interval / options.release).exp();
+
+        let state = &mut *self.state.borrow_mut();
+        if state.lookahead.get() != options.lookahead {
+            state.lookahead.set(options.lookahead);
+        }
+        let capacity = state.delay.len();
+        let mut reduction = state.reduction;
+        for x in out {
+            let level = x.channels().iter().fold(0.0f32, |acc, &s| acc.max(s.abs()));
+            let target = options.reduction(20.0 * level.log10());
+            let coeff = if target > reduction { attack } else { release };
+            reduction += coeff * (target - reduction);
+
+            state.delay[state.cursor] = core::mem::replace(x, T::Frame::ZERO);
+            let mut lookahead =
+                (state.lookahead.get() / interval).clamp(0.0, (capacity - 2) as f32);
+            if state.lookahead.progress() >= 1.0 {
+                // Read whole frames when not gliding, so that steady lookahead doesn't filter
+                lookahead = (lookahead + 0.5).trunc();
+            }
+            let i = lookahead.trunc() as usize;
+            let a = &state.delay[(state.cursor + capacity - i) % capacity];
+            let b = &state.delay[(state.cursor + capacity - i - 1) % capacity];
+            let delayed = frame::lerp(a, b, lookahead.fract());
+            *x = frame::scale(&delayed, 10.0f32.powf((options.makeup - reduction) / 20.0));
+            state.cursor = (state.cursor + 1) % capacity;
+            state.lookahead.advance(interval / SMOOTHING_PERIOD);
+        }
+        state.reduction = reduction;
+        self.reduction.store(reduction.to_bits(), Ordering::Relaxed);
+    }
+
+    fn remaining(&self) -> f32 {
+        self.inner.remaining() + Self::MAX_LOOKAHEAD
+    }
+
+    #[inline]
+    fn handle_dropped(&self) {
+        self.inner.handle_dropped();
+    }
+}
+
+impl<T: Signal + ?Sized> Filter for Compressor<T> {
+    type Inner = T;
+    fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+/// Thread-safe control for a [`Compressor`] filter
+pub struct CompressorControl<'a> {
+    options: &'a Swap<CompressorOptions>,
+    reduction: &'a AtomicU32,
+}
+
+unsafe impl<'a, T: Signal + 'a> Controlled<'a> for Compressor<T> {
+    type Control = CompressorControl<'a>;
+
+    unsafe fn make_control(signal: &'a Compressor<T>) -> Self::Control {
+        CompressorControl {
+            options: &signal.options,
+            reduction: &signal.reduction,
+        }
+    }
+}
+
+impl<'a> CompressorControl<'a> {
+    /// Replace the filter's configuration
+    pub fn set_options(&mut self, options: CompressorOptions) {
+        unsafe {
+            *self.options.pending() = options;
+        }
+        self.options.flush();
+    }
+
+    /// Get the gain reduction most recently applied, in decibels, not including makeup gain
+    pub fn gain_reduction(&self) -> f32 {
+        f32::from_bits(self.reduction.load(Ordering::Relaxed))
+    }
+}
+
+/// Seconds over which changes to the lookahead are glided
+const SMOOTHING_PERIOD: f32 = 0.05;
+
+/// Highest sample rate at which the full lookahead is available
+pub(crate) const MAX_SAMPLE_RATE: f32 = 192_000.0;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Constant;
+    use core::cell::Cell;
+
+    /// Emits 0, 1, 2, ...
+    struct Ramp(Cell<f32>);
+
+    impl Signal for Ramp {
+        type Frame = f32;
+
+        fn sample(&self, _: f32, out: &mut [f32]) {
+            for x in out {
+                *x = self.0.get();
+                self.0.set(*x + 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn knee() {
+        let options = CompressorOptions {
+            threshold: -20.0,
+            ratio: 4.0,
+            knee: 10.0,
+            ..CompressorOptions::default()
+        };
+        assert_eq!(options.reduction(-30.0), 0.0);
+        assert_eq!(options.reduction(-25.0), 0.0);
+        assert!((options.reduction(-20.0) - 0.9375).abs() < 1e-6);
+        assert_eq!(options.reduction(-15.0), 3.75);
+        assert_eq!(options.reduction(0.0), 15.0);
+    }
+
+    #[test]
+    fn lookahead() {
+        let options = CompressorOptions {
+            threshold: -20.0,
+            ratio: 4.0,
+            knee: 0.0,
+            attack: 0.0,
+            release: 0.0,
+            makeup: 3.0,
+            lookahead: 0.01,
+        };
+        let s = Compressor::new(Constant(1.0), options);
+        let mut buf = [0.0; 20];
+        s.sample(0.001, &mut buf);
+        assert_eq!(buf[..10], [0.0; 10]);
+        let expected = 10.0f32.powf((3.0 - 15.0) / 20.0);
+        for x in &buf[10..] {
+            assert!((x - expected).abs() < 1e-6);
+        }
+        let control = unsafe { Compressor::make_control(&s) };
+        assert_eq!(control.gain_reduction(), 15.0);
+
+        // Changes glide rather than jumping
+        let options = CompressorOptions {
+            threshold: 100.0,
+            makeup: 0.0,
+            lookahead: 0.0,
+            ..options
+        };
+        let s = Compressor::new(Ramp(Cell::new(0.0)), options);
+        let mut control = unsafe { Compressor::make_control(&s) };
+        s.sample(0.001, &mut buf);
+        assert_eq!(buf[19], 19.0);
+        control.set_options(CompressorOptions {
+            lookahead: 0.01,
+            ..options
+        });
+        let mut buf = [0.0; 100];
+        s.sample(0.001, &mut buf);
+        assert!(buf.windows(2).all(|w| (0.0..=1.0).contains(&(w[1] - w[0]))));
+        assert_eq!(buf[99], 119.0 - 10.0);
+    }
+}
@@ -0,0 +1,347 @@
+use alloc::{boxed::Box, sync::Arc};
+use core::{cell::RefCell, marker::PhantomData};
+
+use crate::{
+    fft::{Complex, Fft},
+    swap::Swap,
+    Controlled, Filter, Frame, Frames, Signal,
+};
+
+/// Convolves a signal with a recorded impulse response, e.g. to place it in a real space
+///
+/// Uses uniformly partitioned FFT convolution, so that impulse responses several seconds long can
+/// be applied in real time. The output is delayed by the block size, in samples; smaller blocks
+/// reduce latency at the cost of more CPU time.
+///
+/// The impulse response should have the same sample rate as the output, and each of its channels
+/// is applied to the corresponding channel of the signal. It can be replaced during playback with
+/// [`ConvolverControl::set_impulse_response`], which prepares it on the calling thread, so the
+/// audio thread never allocates.
+pub struct Convolver<T: Signal + ?Sized> {
+    fft: Fft,
+    block_size: usize,
+    /// Number of partitions for which history is kept
+    capacity: usize,
+    impulse_response: Swap<ImpulseResponse>,
+    state: RefCell<State<T::Frame>>,
+    inner: T,
+}
+
+/// An impulse response, transformed for convolution
+#[derive(Clone, Default)]
+struct ImpulseResponse {
+    /// Spectrum of each block of each channel, scaled to undo the inverse transform's gain
+    spectra: Box<[Complex]>,
+    partitions: usize,
+    /// Seconds taken for an impulse to pass through entirely, including latency
+    tail: f32,
+}
+
+impl ImpulseResponse {
+    fn new<F: Frame>(fft: &Fft, frames: &Frames<F>, capacity: usize) -> Self {
+        let n = fft.len();
+        let block_size = n / 2;
+        let channels = F::ZERO.channels().len();
+        let partitions = blocks(frames.len(), block_size).clamp(1, capacity);
+        let mut spectra = (0..channels * partitions * n)
+            .map(|_| Complex::ZERO)
+            .collect::<Box<[_]>>();
+        for (c, channel) in spectra.chunks_mut(partitions * n).enumerate() {
+            for (p, spectrum) in channel.chunks_mut(n).enumerate() {
+                // Zero-padded to twice the block size, so that overlap-save produces a linear
+                // convolution
+                let block = frames.iter().skip(p * block_size).take(block_size);
+                for (x, frame) in spectrum.iter_mut().zip(block) {
+                    x.re = frame.channels()[c] / n as f32;
+                }
+                fft.forward(spectrum);
+            }
+        }
+        let length = frames.len().min(partitions * block_size);
+        Self {
+            spectra,
+            partitions,
+            tail: (length + block_size) as f32 / frames.rate() as f32,
+        }
+    }
+}
+
+struct State<F> {
+    /// The previous block of input followed by the current one
+    input: Box<[F]>,
+    /// Output for the previous block of input
+    output: Box<[F]>,
+    /// Index in the current block of the next frame
+    cursor: usize,
+    /// Spectra of recent input blocks for each channel, in a ring buffer of `capacity` blocks
+    history: Box<[Complex]>,
+    /// Index in `history` of the most recent block
+    head: usize,
+    scratch: Box<[Complex]>,
+    accumulator: Box<[Complex]>,
+}
+
+impl<T: Signal> Convolver<T>
+where
+    T::Frame: Frame + Copy,
+{
+    /// Convolve `signal` with `impulse_response`, processing `block_size` frames at a time
+    ///
+    /// Impulse responses passed to [`ConvolverControl::set_impulse_response`] later are truncated
+    /// to the length of `impulse_response`, rounded up to a whole number of blocks. Use
+    /// [`with_max_len`](Self::with_max_len) to allow for longer ones.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block_size` is not a power of two.
+    pub fn new(signal: T, impulse_response: Arc<Frames<T::Frame>>, block_size: usize) -> Self {
+        let max_len = impulse_response.len();
+        Self::with_max_len(signal, impulse_response, block_size, max_len)
+    }
+
+    /// Like [`new`](Self::new), but with room for impulse responses of up to `max_len` frames
+    ///
+    /// Memory use and processing time grow with `max_len`, regardless of the length of the
+    /// response in use.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block_size` is not a power of two.
+    pub fn with_max_len(
+        signal: T,
+        impulse_response: Arc<Frames<T::Frame>>,
+        block_size: usize,
+        max_len: usize,
+    ) -> Self {
+        assert!(
+            block_size.is_power_of_two(),
+            "block size must be a power of two"
+        );
+        let n = 2 * block_size;
+        let fft = Fft::new(n);
+        let channels = T::Frame::ZERO.channels().len();
+        let capacity = blocks(max_len.max(impulse_response.len()), block_size).max(1);
+        let shared = Swap::<ImpulseResponse>::default();
+        unsafe {
+            *shared.pending() = ImpulseResponse::new(&fft, &impulse_response, capacity);
+        }
+        shared.flush();
+        Self {
+            fft,
+            block_size,
+            capacity,
+            impulse_response: shared,
+            state: RefCell::new(State {
+                input: (0..n).map(|_| T::Frame::ZERO).collect(),
+                output: (0..block_size).map(|_| T::Frame::ZERO).collect(),
+                cursor: 0,
+                history: (0..channels * capacity * n)
+                    .map(|_| Complex::ZERO)
+                    .collect(),
+                head: 0,
+                scratch: (0..n).map(|_| Complex::ZERO).collect(),
+                accumulator: (0..n).map(|_| Complex::ZERO).collect(),
+            }),
+            inner: signal,
+        }
+    }
+}
+
+impl<T: Signal + ?Sized> Convolver<T>
+where
+    T::Frame: Frame + Copy,
+{
+    /// Compute the output for the block of input that was just completed
+    fn process(&self, state: &mut State<T::Frame>, ir: &ImpulseResponse) {
+        let n = self.fft.len();
+        let block_size = self.block_size;
+        state.head = (state.head + 1) % self.capacity;
+        let history = state.history.chunks_mut(self.capacity * n);
+        let spectra = ir.spectra.chunks(ir.partitions * n);
+        for (c, (history, spectra)) in history.zip(spectra).enumerate() {
+            for (x, frame) in state.scratch.iter_mut().zip(state.input.iter()) {
+                *x = Complex::new(frame.channels()[c], 0.0);
+            }
+            self.fft.forward(&mut state.scratch);
+            history[state.head * n..(state.head + 1) * n].copy_from_slice(&state.scratch);
+
+            // Multiply each block of the impulse response by the input from as many blocks ago
+            for x in state.accumulator.iter_mut() {
+                *x = Complex::ZERO;
+            }
+            for (p, spectrum) in spectra.chunks(n).enumerate() {
+                let slot = (state.head + self.capacity - p) % self.capacity;
+                let input = &history[slot * n..(slot + 1) * n];
+                for ((acc, &x), &h) in state.accumulator.iter_mut().zip(input).zip(spectrum) {
+                    *acc = *acc + x * h;
+                }
+            }
+            self.fft.inverse(&mut state.accumulator);
+
+            // The first half is corrupted by circular wraparound, so only the second is used
+            for (frame, x) in state
+                .output
+                .iter_mut()
+                .zip(&state.accumulator[block_size..])
+            {
+                frame.channels_mut()[c] = x.re;
+            }
+        }
+        state.input.copy_within(block_size.., 0);
+    }
+}
+
+impl<T: Signal + ?Sized> Signal for Convolver<T>
+where
+    T::Frame: Frame + Copy,
+{
+    type Frame = T::Frame;
+
+    fn sample(&self, interval: f32, out: &mut [T::Frame]) {
+        self.inner.sample(interval, out);
+        self.impulse_response.refresh();
+        let ir = unsafe { &*self.impulse_response.received() };
+        let state = &mut *self.state.borrow_mut();
+        for x in out {
+            let cursor = state.cursor;
+            state.input[self.block_size + cursor] = *x;
+            *x = state.output[cursor];
+            state.cursor += 1;
+            if state.cursor == self.block_size {
+                self.process(state, ir);
+                state.cursor = 0;
+            }
+        }
+    }
+
+    fn remaining(&self) -> f32 {
+        self.impulse_response.refresh();
+        let ir = unsafe { &*self.impulse_response.received() };
+        self.inner.remaining() + ir.tail
+    }
+
+    #[inline]
+    fn handle_dropped(&self) {
+        self.inner.handle_dropped();
+    }
+}
+
+impl<T: Signal + ?Sized> Filter for Convolver<T> {
+    type Inner = T;
+    fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+/// Thread-safe control for a [`Convolver`] filter
+pub struct ConvolverControl<'a, F> {
+    fft: &'a Fft,
+    capacity: usize,
+    impulse_response: &'a Swap<ImpulseResponse>,
+    frame: PhantomData<fn(F)>,
+}
+
+unsafe impl<'a, T: Signal + 'a> Controlled<'a> for Convolver<T> {
+    type Control = ConvolverControl<'a, T::Frame>;
+
+    unsafe fn make_control(signal: &'a Convolver<T>) -> Self::Control {
+        ConvolverControl {
+            fft: &signal.fft,
+            capacity: signal.capacity,
+            impulse_response: &signal.impulse_response,
+            frame: PhantomData,
+        }
+    }
+}
+
+impl<'a, F: Frame> ConvolverControl<'a, F> {
+    /// Replace the impulse response
+    ///
+    /// The transform is computed on the calling thread. Reverberation already in progress
+    /// continues with the new response.
+    ///
+    /// Responses longer than the filter has room for, as determined when it was constructed, are
+    /// truncated, in which case `false` is returned.
+    pub fn set_impulse_response(&mut self, impulse_response: &Frames<F>) -> bool {
+        unsafe {
+            *self.impulse_response.pending() =
+                ImpulseResponse::new(self.fft, impulse_response, self.capacity);
+        }
+        self.impulse_response.flush();
+        blocks(impulse_response.len(), self.fft.len() / 2) <= self.capacity
+    }
+}
+
+/// Number of `block_size` blocks needed to hold `len` frames
+// Avoids `usize::div_ceil`, which needs a newer compiler than the rest of the crate
+#[allow(clippy::manual_div_ceil)]
+fn blocks(len: usize, block_size: usize) -> usize {
+    (len + block_size - 1) / block_size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FramesSignal;
+
+    #[test]
+    fn direct() {
+        let ir = (0..37)
+            .map(|i| ((i * 7 % 11) as f32 - 5.0) / 5.0)
+            .collect::<alloc::vec::Vec<_>>();
+        let input = (0..50)
+            .map(|i| ((i * 3 % 13) as f32 - 6.0) / 6.0)
+            .collect::<alloc::vec::Vec<_>>();
+        let s = Convolver::new(
+            FramesSignal::new(Frames::from_slice(1, &input), 0.0),
+            Frames::from_slice(1, &ir),
+            8,
+        );
+        let mut buf = [0.0; 100];
+        s.sample(1.0, &mut buf);
+        assert_eq!(buf[..8], [0.0; 8]);
+        for (i, &x) in buf[8..].iter().enumerate() {
+            let expected = (0..=i)
+                .map(|j| input.get(j).unwrap_or(&0.0) * ir.get(i - j).unwrap_or(&0.0))
+                .sum::<f32>();
+            assert!((x - expected).abs() < 1e-4, "{}: {} != {}", i, x, expected);
+        }
+    }
+
+    #[test]
+    fn swap() {
+        let s = Convolver::new(
+            FramesSignal::new(Frames::from_slice(1, &[[1.0, 2.0]; 16]), 0.0),
+            Frames::from_slice(1, &[[1.0, 1.0], [0.0, 0.0]]),
+            2,
+        );
+        let mut control = unsafe { Convolver::make_control(&s) };
+        let mut buf = [[0.0; 2]; 4];
+        s.sample(1.0, &mut buf);
+        assert_eq!(buf[..2], [[0.0; 2]; 2]);
+        assert!((buf[3][0] - 1.0).abs() < 1e-5 && (buf[3][1] - 2.0).abs() < 1e-5);
+
+        // Longer responses are truncated
+        let ir = Frames::from_slice(1, &[[0.5, -1.0], [0.5, 0.0], [1.0; 2]]);
+        assert!(!control.set_impulse_response(&ir));
+        s.sample(1.0, &mut buf);
+        let expected = [[1.0, 2.0], [1.0, 2.0], [1.0, -2.0], [1.0, -2.0]];
+        for (x, y) in buf.iter().zip(&expected) {
+            assert!((x[0] - y[0]).abs() < 1e-5 && (x[1] - y[1]).abs() < 1e-5);
+        }
+        // Half the input remains, followed by the truncated response and the latency
+        assert_eq!(s.remaining(), 8.0 + 2.0 + 2.0);
+
+        // Unless there's room for them
+        let s = Convolver::with_max_len(
+            FramesSignal::new(Frames::from_slice(1, &[[1.0, 2.0]; 16]), 0.0),
+            Frames::from_slice(1, &[[1.0, 1.0], [0.0, 0.0]]),
+            2,
+            3,
+        );
+        let mut control = unsafe { Convolver::make_control(&s) };
+        assert!(control.set_impulse_response(&ir));
+        s.sample(1.0, &mut buf);
+        assert_eq!(s.remaining(), 12.0 + 3.0 + 2.0);
+    }
+}
@@ -0,0 +1,247 @@
+use alloc::boxed::Box;
+use core::cell::RefCell;
+
+use crate::{
+    biquad::{self, Smoother, CHUNK_SIZE},
+    compressor::MAX_SAMPLE_RATE,
+    frame,
+    math::Float,
+    ring::Ring,
+    swap::Swap,
+    BiquadOptions, Controlled, Filter, Frame, Signal, Smoothed,
+};
+
+/// Adds echoes of a signal, each a fixed time after the last
+///
+/// Each echo is fed back into the delay line, scaled by the feedback gain and optionally shaped by
+/// a filter, e.g. a low-pass filter to make successive echoes progressively darker. Changes to the
+/// delay time are glided over rather than applied immediately, avoiding clicks at the cost of a
+/// brief tape-like pitch shift. The echoes continue after the inner signal finishes until they've
+/// decayed to inaudibility, and [`remaining`](Signal::remaining) accounts for them.
+///
+/// In ping-pong mode, which applies only to stereo signals, the input is averaged into the left
+/// channel, and each echo is sent to the opposite channel from the last.
+pub struct Delay<T: Signal + ?Sized> {
+    options: Swap<DelayOptions>,
+    max_time: f32,
+    state: RefCell<State<T::Frame>>,
+    inner: T,
+}
+
+struct State<F> {
+    ring: Ring<F>,
+    /// Delay time in seconds
+    time: Smoothed<f32>,
+    /// Feedback filter parameters
+    smoother: Smoother,
+    /// Feedback filter state
+    filter: Box<[[f32; 2]]>,
+}
+
+impl<T: Signal> Delay<T>
+where
+    T::Frame: Frame + Copy,
+{
+    /// Apply echoes to `signal`, with a delay time of up to `max_time` seconds
+    ///
+    /// Memory proportional to `max_time` is allocated up front, so that the delay time can be
+    /// changed freely during playback.
+    pub fn new(signal: T, max_time: f32, options: DelayOptions) -> Self {
+        let capacity = (max_time * MAX_SAMPLE_RATE).ceil() as usize + 2;
+        Self {
+            options: Swap::new(options),
+            max_time,
+            state: RefCell::new(State {
+                ring: Ring::new(capacity),
+                time: Smoothed::new(options.time.min(max_time)),
+                smoother: Smoother::new(options.filter.unwrap_or_default()),
+                filter: biquad::state::<T::Frame>(),
+            }),
+            inner: signal,
+        }
+    }
+}
+
+/// Configuration for a [`Delay`] filter
+#[derive(Debug, Copy, Clone)]
+pub struct DelayOptions {
+    /// Seconds between echoes. Limited to the maximum passed to [`Delay::new`].
+    pub time: f32,
+    /// Amplitude ratio of each echo to the last. Should be less than 1.
+    pub feedback: f32,
+    /// Proportion of the output made up of echoes, from 0 (dry) to 1 (wet)
+    pub mix: f32,
+    /// Whether successive echoes alternate between the left and right channels
+    pub ping_pong: bool,
+    /// Filter applied to each echo before it's fed back
+    pub filter: Option<BiquadOptions>,
+}
+
+impl Default for DelayOptions {
+    fn default() -> Self {
+        Self {
+            time: 0.25,
+            feedback: 0.4,
+            mix: 0.3,
+            ping_pong: false,
+            filter: None,
+        }
+    }
+}
+
+impl<T: Signal + ?Sized> Signal for Delay<T>
+where
+    T::Frame: Frame + Copy,
+{
+    type Frame = T::Frame;
+
+    #[allow(clippy::float_cmp)]
+    fn sample(&self, interval: f32, out: &mut [T::Frame]) {
+        self.inner.sample(interval, out);
+        self.options.refresh();
+        let options = unsafe { *self.options.received() };
+        let state = &mut *self.state.borrow_mut();
+        let time = options.time.min(self.max_time);
+        if state.time.get() != time {
+            state.time.set(time);
+        }
+        if let Some(filter) = options.filter {
+            state.smoother.set(filter);
+        }
+        // Ping-pong operates on channel pairs, so other layouts echo normally
+        let ping_pong = options.ping_pong && T::Frame::ZERO.channels().len() == 2;
+        let max_delay = (state.ring.capacity() - 2) as f32;
+
+        for chunk in out.chunks_mut(CHUNK_SIZE) {
+            let coefficients = state.smoother.coefficients(interval);
+            for x in chunk.iter_mut() {
+                let delay = (state.time.get() / interval).clamp(1.0, max_delay);
+                let echo = state.ring.sample(1, -delay);
+
+                let mut feedback = match options.filter {
+                    Some(_) => coefficients.process(&mut state.filter, &echo),
+                    None => echo,
+                };
+                feedback = frame::scale(&feedback, options.feedback);
+                let input = if ping_pong {
+                    feedback.channels_mut().swap(0, 1);
+                    let mut mono = T::Frame::ZERO;
+                    mono.channels_mut()[0] =
+                        x.channels().iter().sum::<f32>() / x.channels().len() as f32;
+                    mono
+                } else {
+                    *x
+                };
+                state.ring.push(frame::mix(&input, &feedback));
+
+                *x = frame::lerp(x, &echo, options.mix);
+                state.time.advance(interval / SMOOTHING_PERIOD);
+            }
+            state.smoother.advance(interval * chunk.len() as f32);
+        }
+    }
+
+    fn remaining(&self) -> f32 {
+        self.options.refresh();
+        let options = unsafe { &*self.options.received() };
+        self.inner.remaining() + echo_tail(options.time.min(self.max_time), options.feedback)
+    }
+
+    #[inline]
+    fn handle_dropped(&self) {
+        self.inner.handle_dropped();
+    }
+}
+
+impl<T: Signal + ?Sized> Filter for Delay<T> {
+    type Inner = T;
+    fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+/// Thread-safe control for a [`Delay`] filter
+pub struct DelayControl<'a>(&'a Swap<DelayOptions>);
+
+unsafe impl<'a, T: Signal + 'a> Controlled<'a> for Delay<T> {
+    type Control = DelayControl<'a>;
+
+    unsafe fn make_control(signal: &'a Delay<T>) -> Self::Control {
+        DelayControl(&signal.options)
+    }
+}
+
+impl<'a> DelayControl<'a> {
+    /// Replace the filter's configuration
+    pub fn set_options(&mut self, options: DelayOptions) {
+        unsafe {
+            *self.0.pending() = options;
+        }
+        self.0.flush();
+    }
+}
+
+/// Seconds over which changes to the delay time are glided
+const SMOOTHING_PERIOD: f32 = 0.1;
+
+/// Seconds for the first echo after `time` seconds, then for echoes fed back with gain `feedback`
+/// to decay by 60 dB
+pub(crate) fn echo_tail(time: f32, feedback: f32) -> f32 {
+    let feedback = feedback.abs();
+    if feedback >= 1.0 {
+        return f32::INFINITY;
+    }
+    if feedback == 0.0 {
+        return time;
+    }
+    time * (1.0 - 3.0 / feedback.log10())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Frames, FramesSignal};
+
+    #[test]
+    fn echoes() {
+        let options = DelayOptions {
+            time: 0.3,
+            feedback: 0.5,
+            mix: 1.0,
+            ..DelayOptions::default()
+        };
+        let s = Delay::new(
+            FramesSignal::new(Frames::from_slice(10, &[1.0]), 0.0),
+            1.0,
+            options,
+        );
+        let mut buf = [0.0; 10];
+        s.sample(0.1, &mut buf);
+        let expected = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.5, 0.0, 0.0, 0.25];
+        for (x, y) in buf.iter().zip(&expected) {
+            assert!((x - y).abs() < 1e-6);
+        }
+        // The inner signal is long over, but the echoes continue
+        assert!(s.remaining() > 2.0);
+    }
+
+    #[test]
+    fn ping_pong() {
+        let options = DelayOptions {
+            time: 0.1,
+            feedback: 1.0,
+            mix: 1.0,
+            ping_pong: true,
+            filter: None,
+        };
+        let s = Delay::new(
+            FramesSignal::new(Frames::from_slice(10, &[[0.5, 0.5]]), 0.0),
+            1.0,
+            options,
+        );
+        let mut buf = [[0.0; 2]; 4];
+        s.sample(0.1, &mut buf);
+        assert_eq!(buf, [[0.0, 0.0], [0.5, 0.0], [0.0, 0.5], [0.5, 0.0]]);
+        assert_eq!(s.remaining(), f32::INFINITY);
+    }
+}
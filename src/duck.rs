@@ -0,0 +1,193 @@
+use core::{
+    cell::Cell,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use crate::{frame, math::Float, swap::Swap, Controlled, Filter, Frame, Signal};
+
+/// Reduces the level of a signal while a key signal is loud
+///
+/// Outputs the key signal unmodified, mixed with the inner signal after gain reduction. An
+/// envelope follower tracks the peak level of the key, and whenever it exceeds the threshold the
+/// inner signal is attenuated according to the configured ratio.
+///
+/// Useful for automatically lowering music and ambience while dialogue plays: use one
+/// [`Mixer`](crate::Mixer) as the key and another as the inner signal, then play dialogue through
+/// the key mixer's controls, obtained with [`DuckControl::key`].
+pub struct Duck<K, T: ?Sized> {
+    options: Swap<DuckOptions>,
+    /// Smoothed peak level of the key signal
+    envelope: Cell<f32>,
+    /// Most recent gain reduction in decibels, for reporting
+    reduction: AtomicU32,
+    key: K,
+    inner: T,
+}
+
+impl<K, T> Duck<K, T> {
+    /// Duck `signal` by the level of `key`
+    pub fn new(key: K, signal: T, options: DuckOptions) -> Self {
+        Self {
+            options: Swap::new(options),
+            envelope: Cell::new(0.0),
+            reduction: AtomicU32::new(0.0f32.to_bits()),
+            key,
+            inner: signal,
+        }
+    }
+}
+
+/// Configuration for a [`Duck`] filter
+#[derive(Debug, Copy, Clone)]
+pub struct DuckOptions {
+    /// Key level, in decibels, above which gain reduction begins
+    pub threshold: f32,
+    /// Proportion by which the key's level above the threshold is reduced. For example, with a
+    /// ratio of 4, a key 8 dB over the threshold reduces the inner signal's gain by 6 dB.
+    pub ratio: f32,
+    /// Time constant, in seconds, with which gain reduction increases
+    pub attack: f32,
+    /// Time constant, in seconds, with which gain reduction recovers
+    pub release: f32,
+}
+
+impl Default for DuckOptions {
+    fn default() -> Self {
+        Self {
+            threshold: -30.0,
+            ratio: 4.0,
+            attack: 0.05,
+            release: 0.5,
+        }
+    }
+}
+
+impl<K, T> Signal for Duck<K, T>
+where
+    K: Signal<Frame = T::Frame>,
+    T: Signal + ?Sized,
+    T::Frame: Frame,
+{
+    type Frame = T::Frame;
+
+    fn sample(&self, interval: f32, out: &mut [T::Frame]) {
+        const CHUNK_SIZE: usize = 256;
+
+        self.options.refresh();
+        let options = unsafe { *self.options.received() };
+        let attack = 1.0 - (-interval / options.attack).exp();
+        let release = 1.0 - (-interval / options.release).exp();
+        let slope = 1.0 - 1.0 / options.ratio;
+
+        let mut buf = [T::Frame::ZERO; CHUNK_SIZE];
+        let mut envelope = self.envelope.get();
+        let mut reduction = 0.0;
+        for chunk in out.chunks_mut(CHUNK_SIZE) {
+            let buf = &mut buf[..chunk.len()];
+            self.inner.sample(interval, buf);
+            self.key.sample(interval, chunk);
+            for (x, o) in buf.iter().zip(chunk) {
+                let level = o.channels().iter().fold(0.0f32, |acc, &s| acc.max(s.abs()));
+                let coeff = if level > envelope { attack } else { release };
+                envelope += coeff * (level - envelope);
+                let over = 20.0 * envelope.log10() - options.threshold;
+                reduction = if over > 0.0 { over * slope } else { 0.0 };
+                let gain = 10.0f32.powf(-reduction / 20.0);
+                *o = frame::mix(o, &frame::scale(x, gain));
+            }
+        }
+        self.envelope.set(envelope);
+        self.reduction.store(reduction.to_bits(), Ordering::Relaxed);
+    }
+
+    fn remaining(&self) -> f32 {
+        self.key.remaining().max(self.inner.remaining())
+    }
+
+    #[inline]
+    fn handle_dropped(&self) {
+        self.key.handle_dropped();
+        self.inner.handle_dropped();
+    }
+}
+
+impl<K, T: ?Sized> Filter for Duck<K, T> {
+    type Inner = T;
+    fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+/// Thread-safe control for a [`Duck`] filter
+pub struct DuckControl<'a, K> {
+    options: &'a Swap<DuckOptions>,
+    reduction: &'a AtomicU32,
+    key: &'a K,
+}
+
+unsafe impl<'a, K: 'a, T: 'a> Controlled<'a> for Duck<K, T> {
+    type Control = DuckControl<'a, K>;
+
+    unsafe fn make_control(signal: &'a Duck<K, T>) -> Self::Control {
+        DuckControl {
+            options: &signal.options,
+            reduction: &signal.reduction,
+            key: &signal.key,
+        }
+    }
+}
+
+impl<'a, K> DuckControl<'a, K> {
+    /// Replace the filter's configuration
+    pub fn set_options(&mut self, options: DuckOptions) {
+        unsafe {
+            *self.options.pending() = options;
+        }
+        self.options.flush();
+    }
+
+    /// Get the gain reduction most recently applied to the inner signal, in decibels
+    pub fn gain_reduction(&self) -> f32 {
+        f32::from_bits(self.reduction.load(Ordering::Relaxed))
+    }
+
+    /// Get the control for the key signal
+    ///
+    /// For example, if the key is a [`Mixer`](crate::Mixer), this can be used to play dialogue
+    /// that ducks the inner signal.
+    pub fn key<'b>(&'b mut self) -> K::Control
+    where
+        K: Controlled<'b>,
+    {
+        unsafe { K::make_control(self.key) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Constant;
+
+    #[test]
+    fn smoke() {
+        let options = DuckOptions {
+            threshold: -20.0,
+            ratio: f32::INFINITY,
+            attack: 1e-3,
+            release: 1e-3,
+        };
+        let mut s = Duck::new(Constant::new(0.0), Constant::new(1.0), options);
+        let mut buf = [0.0; 300];
+
+        // Silent key doesn't affect the signal
+        s.sample(0.1, &mut buf);
+        assert_eq!(buf, [1.0; 300]);
+
+        // Loud key reduces the signal
+        s.key.0 = 1.0;
+        s.sample(0.1, &mut buf);
+        let control = unsafe { Duck::make_control(&s) };
+        assert!((control.gain_reduction() - 20.0).abs() < 1e-3);
+        assert!((buf[299] - 1.1).abs() < 1e-3);
+    }
+}
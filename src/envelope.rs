@@ -0,0 +1,425 @@
+use core::{
+    cell::Cell,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use crate::{frame, math::Float, swap::Swap, Controlled, Filter, Frame, Seek, Signal};
+
+/// Shapes the level of a signal with an attack/decay/sustain/release envelope
+///
+/// The gate starts open: the level rises from silence to full over the attack time, falls to the
+/// sustain level over the decay time, and stays there until [`EnvelopeControl::gate_off`] is
+/// called, after which it falls to silence over the release time. The signal finishes once the
+/// release is complete, so voices played on a [`Mixer`](crate::Mixer) are removed automatically.
+///
+/// Reopening the gate with [`EnvelopeControl::gate_on`] restarts the attack from the current level,
+/// avoiding discontinuities.
+pub struct Envelope<T: ?Sized> {
+    options: Swap<EnvelopeOptions>,
+    gate: Gate,
+    adsr: Cell<Adsr>,
+    inner: T,
+}
+
+impl<T> Envelope<T> {
+    /// Apply an envelope to `signal`
+    pub fn new(signal: T, options: EnvelopeOptions) -> Self {
+        let mut adsr = Adsr::new();
+        adsr.gate_on(&options);
+        Self {
+            options: Swap::new(options),
+            gate: Gate::new(),
+            adsr: Cell::new(adsr),
+            inner: signal,
+        }
+    }
+}
+
+impl<T: ?Sized> Envelope<T> {
+    /// Apply gate changes made through the control
+    fn refresh(&self) -> EnvelopeOptions {
+        self.options.refresh();
+        let options = unsafe { *self.options.received() };
+        let mut adsr = self.adsr.get();
+        adsr.update(self.gate.poll(), &options);
+        self.adsr.set(adsr);
+        options
+    }
+}
+
+/// Configuration for an [`Envelope`] filter
+#[derive(Debug, Copy, Clone)]
+pub struct EnvelopeOptions {
+    /// Seconds taken to rise from silence to full level after the gate opens
+    pub attack: f32,
+    /// Seconds taken to fall from full to sustain level after the attack
+    pub decay: f32,
+    /// Level, between 0 and 1, held while the gate remains open
+    pub sustain: f32,
+    /// Seconds taken to fall to silence after the gate closes
+    pub release: f32,
+    /// Shape of each transition
+    pub curve: Curve,
+}
+
+impl Default for EnvelopeOptions {
+    fn default() -> Self {
+        Self {
+            attack: 0.01,
+            decay: 0.1,
+            sustain: 0.7,
+            release: 0.3,
+            curve: Curve::Linear,
+        }
+    }
+}
+
+/// Shape of a transition between two values
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Curve {
+    /// Proceed at a constant rate
+    #[default]
+    Linear,
+    /// Start quickly and slow down approaching the target, like an analog envelope
+    Exponential,
+    /// Start slowly and speed up approaching the target
+    Logarithmic,
+    /// Start and end slowly, with the fastest change in the middle
+    SCurve,
+}
+
+impl Curve {
+    /// Map linear progress in [0, 1] to shaped progress in [0, 1]
+    pub(crate) fn apply(self, x: f32) -> f32 {
+        // Steepness of the exponential curves; e^-4.6 is about 1%, so the unnormalized curve is
+        // within 1% of the target by the end
+        const K: f32 = 4.6;
+        let x = x.clamp(0.0, 1.0);
+        match self {
+            Curve::Linear => x,
+            Curve::Exponential => (1.0 - (-K * x).exp()) / (1.0 - (-K).exp()),
+            Curve::Logarithmic => 1.0 - Curve::Exponential.apply(1.0 - x),
+            Curve::SCurve => x * x * (3.0 - 2.0 * x),
+        }
+    }
+}
+
+impl<T: Signal + ?Sized> Signal for Envelope<T>
+where
+    T::Frame: Frame,
+{
+    type Frame = T::Frame;
+
+    fn sample(&self, interval: f32, out: &mut [T::Frame]) {
+        let options = self.refresh();
+        self.inner.sample(interval, out);
+        let mut adsr = self.adsr.get();
+        for x in out {
+            *x = frame::scale(x, adsr.level(&options));
+            adsr.advance(&options, interval);
+        }
+        self.adsr.set(adsr);
+    }
+
+    fn remaining(&self) -> f32 {
+        let options = unsafe { *self.options.received() };
+        // Account for gate changes that haven't been sampled yet
+        let mut adsr = self.adsr.get();
+        adsr.update(self.gate.peek(), &options);
+        adsr.remaining(&options).min(self.inner.remaining())
+    }
+
+    #[inline]
+    fn handle_dropped(&self) {
+        self.inner.handle_dropped();
+    }
+}
+
+impl<T: ?Sized> Filter for Envelope<T> {
+    type Inner = T;
+    fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: ?Sized + Seek> Seek for Envelope<T>
+where
+    T::Frame: Frame,
+{
+    fn seek(&self, seconds: f32) {
+        self.inner.seek(seconds);
+        let options = self.refresh();
+        let mut adsr = self.adsr.get();
+        adsr.advance(&options, seconds);
+        self.adsr.set(adsr);
+    }
+}
+
+/// Thread-safe control for an [`Envelope`] filter
+pub struct EnvelopeControl<'a> {
+    options: &'a Swap<EnvelopeOptions>,
+    gate: &'a Gate,
+}
+
+unsafe impl<'a, T: 'a> Controlled<'a> for Envelope<T> {
+    type Control = EnvelopeControl<'a>;
+
+    unsafe fn make_control(signal: &'a Envelope<T>) -> Self::Control {
+        EnvelopeControl {
+            options: &signal.options,
+            gate: &signal.gate,
+        }
+    }
+}
+
+impl<'a> EnvelopeControl<'a> {
+    /// Open the gate, restarting the attack from the current level
+    pub fn gate_on(&mut self) {
+        self.gate.open();
+    }
+
+    /// Close the gate, beginning the release
+    pub fn gate_off(&mut self) {
+        self.gate.close();
+    }
+
+    /// Whether the gate is open
+    pub fn is_gate_on(&self) -> bool {
+        self.gate.is_open()
+    }
+
+    /// Replace the envelope's configuration
+    pub fn set_options(&mut self, options: EnvelopeOptions) {
+        unsafe {
+            *self.options.pending() = options;
+        }
+        self.options.flush();
+    }
+}
+
+/// Gate state shared between a signal and its control
+pub(crate) struct Gate {
+    /// Low bit: whether the gate is open. Remaining bits: number of times the gate was opened.
+    shared: AtomicU32,
+    /// Value of `shared` as of the last call to `poll`
+    seen: Cell<u32>,
+}
+
+impl Gate {
+    /// Construct an open gate
+    pub(crate) fn new() -> Self {
+        Self {
+            shared: AtomicU32::new(OPEN),
+            seen: Cell::new(OPEN),
+        }
+    }
+
+    /// Open the gate, even if already open. Control only.
+    pub(crate) fn open(&self) {
+        let _ = self
+            .shared
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |x| {
+                Some((x | OPEN).wrapping_add(2))
+            });
+    }
+
+    /// Close the gate. Control only.
+    pub(crate) fn close(&self) {
+        self.shared.fetch_and(!OPEN, Ordering::Relaxed);
+    }
+
+    pub(crate) fn is_open(&self) -> bool {
+        self.shared.load(Ordering::Relaxed) & OPEN != 0
+    }
+
+    /// Get changes since the last call. Signal only.
+    ///
+    /// Reopening a gate is reported even if it was closed again since, so that brief notes aren't
+    /// lost.
+    pub(crate) fn poll(&self) -> GateEvents {
+        let shared = self.shared.load(Ordering::Relaxed);
+        GateEvents::new(shared, self.seen.replace(shared))
+    }
+
+    /// Get changes since the last call to `poll`, without consuming them. Signal only.
+    pub(crate) fn peek(&self) -> GateEvents {
+        GateEvents::new(self.shared.load(Ordering::Relaxed), self.seen.get())
+    }
+}
+
+const OPEN: u32 = 1;
+
+/// Gate changes reported by [`Gate::poll`]
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct GateEvents {
+    /// Whether the gate was opened
+    opened: bool,
+    /// Whether the gate is currently open
+    open: bool,
+}
+
+impl GateEvents {
+    /// Changes from the gate state `seen` to `shared`
+    fn new(shared: u32, seen: u32) -> Self {
+        Self {
+            opened: shared >> 1 != seen >> 1,
+            open: shared & OPEN != 0,
+        }
+    }
+}
+
+/// State of an attack/decay/sustain/release envelope
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Adsr {
+    stage: Stage,
+    /// Level at the start of the current stage
+    start: f32,
+    /// Seconds since the start of the current stage
+    elapsed: f32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Stage {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    Idle,
+}
+
+impl Adsr {
+    /// Construct a silent envelope with the gate closed
+    pub(crate) fn new() -> Self {
+        Self {
+            stage: Stage::Idle,
+            start: 0.0,
+            elapsed: 0.0,
+        }
+    }
+
+    /// Begin the attack from the current level
+    pub(crate) fn gate_on(&mut self, options: &EnvelopeOptions) {
+        self.start = self.level(options);
+        self.stage = Stage::Attack;
+        self.elapsed = 0.0;
+    }
+
+    /// Begin the release from the current level, unless already released
+    pub(crate) fn gate_off(&mut self, options: &EnvelopeOptions) {
+        if matches!(self.stage, Stage::Release | Stage::Idle) {
+            return;
+        }
+        self.start = self.level(options);
+        self.stage = Stage::Release;
+        self.elapsed = 0.0;
+    }
+
+    /// Apply gate changes
+    pub(crate) fn update(&mut self, events: GateEvents, options: &EnvelopeOptions) {
+        if events.opened {
+            self.gate_on(options);
+        }
+        if !events.open {
+            self.gate_off(options);
+        }
+    }
+
+    /// Current level, between 0 and 1
+    pub(crate) fn level(&self, options: &EnvelopeOptions) -> f32 {
+        let (target, duration) = match self.stage {
+            Stage::Attack => (1.0, options.attack),
+            Stage::Decay => (options.sustain, options.decay),
+            Stage::Sustain => return options.sustain,
+            Stage::Release => (0.0, options.release),
+            Stage::Idle => return 0.0,
+        };
+        let progress = if duration > 0.0 {
+            self.elapsed / duration
+        } else {
+            1.0
+        };
+        self.start + (target - self.start) * options.curve.apply(progress)
+    }
+
+    /// Advance time by `dt` seconds
+    pub(crate) fn advance(&mut self, options: &EnvelopeOptions, dt: f32) {
+        self.elapsed += dt;
+        loop {
+            let (duration, next, start) = match self.stage {
+                Stage::Attack => (options.attack, Stage::Decay, 1.0),
+                Stage::Decay => (options.decay, Stage::Sustain, options.sustain),
+                Stage::Release => (options.release, Stage::Idle, 0.0),
+                Stage::Sustain | Stage::Idle => return,
+            };
+            if self.elapsed < duration {
+                return;
+            }
+            self.elapsed -= duration.max(0.0);
+            self.stage = next;
+            self.start = start;
+        }
+    }
+
+    /// Seconds until the envelope falls silent, if the gate is closed
+    pub(crate) fn remaining(&self, options: &EnvelopeOptions) -> f32 {
+        match self.stage {
+            Stage::Release => options.release - self.elapsed,
+            Stage::Idle => 0.0,
+            _ => f32::INFINITY,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Constant;
+
+    #[test]
+    fn adsr() {
+        let options = EnvelopeOptions {
+            attack: 1.0,
+            decay: 1.0,
+            sustain: 0.5,
+            release: 1.0,
+            curve: Curve::Linear,
+        };
+        let s = Envelope::new(Constant::new(1.0), options);
+        let mut control = unsafe { Envelope::make_control(&s) };
+        let mut buf = [0.0; 6];
+        s.sample(0.5, &mut buf);
+        assert_eq!(buf, [0.0, 0.5, 1.0, 0.75, 0.5, 0.5]);
+        assert_eq!(s.remaining(), f32::INFINITY);
+
+        control.gate_off();
+        let mut buf = [0.0; 3];
+        s.sample(0.5, &mut buf);
+        assert_eq!(buf, [0.5, 0.25, 0.0]);
+        assert_eq!(s.remaining(), 0.0);
+
+        // Retrigger from silence
+        control.gate_on();
+        assert_eq!(s.remaining(), f32::INFINITY);
+        control.gate_off();
+        // The queued release follows the queued attack
+        assert_eq!(s.remaining(), 1.0);
+        control.gate_on();
+        s.sample(0.5, &mut buf);
+        assert_eq!(buf, [0.0, 0.5, 1.0]);
+    }
+
+    #[test]
+    fn curves() {
+        for curve in [
+            Curve::Linear,
+            Curve::Exponential,
+            Curve::Logarithmic,
+            Curve::SCurve,
+        ] {
+            assert_eq!(curve.apply(0.0), 0.0);
+            assert!((curve.apply(1.0) - 1.0).abs() < 1e-6);
+        }
+        assert!(Curve::Exponential.apply(0.5) > 0.5);
+        assert!(Curve::Logarithmic.apply(0.5) < 0.5);
+    }
+}
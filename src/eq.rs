@@ -0,0 +1,215 @@
+use alloc::boxed::Box;
+use core::{
+    cell::RefCell,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{
+    biquad::{self, Coefficients, SharedOptions, Smoother, CHUNK_SIZE, SMOOTHING_PERIOD},
+    frame, BiquadOptions, Controlled, Filter, Frame, Signal, Smoothed,
+};
+
+/// Applies a series of [`Biquad`](crate::Biquad)-style bands to a signal
+///
+/// Each band's parameters are smoothed like those of a [`Biquad`](crate::Biquad). Bands can be
+/// enabled and disabled during playback, in which case they're crossfaded in and out to avoid
+/// clicks. The combined magnitude response can be computed from the control for display.
+pub struct ParametricEq<T: ?Sized> {
+    bands: Box<[Band]>,
+    state: RefCell<Box<[BandState]>>,
+    inner: T,
+}
+
+impl<T: Signal> ParametricEq<T>
+where
+    T::Frame: Frame,
+{
+    /// Apply a band for each of `bands` to `signal`, in order, all initially enabled
+    pub fn new(signal: T, bands: &[BiquadOptions]) -> Self {
+        Self {
+            bands: bands
+                .iter()
+                .map(|&options| Band {
+                    options: SharedOptions::new(options),
+                    enabled: AtomicBool::new(true),
+                })
+                .collect(),
+            state: RefCell::new(
+                bands
+                    .iter()
+                    .map(|&options| BandState {
+                        smoother: Smoother::new(options),
+                        mix: Smoothed::new(1.0),
+                        state: biquad::state::<T::Frame>(),
+                    })
+                    .collect(),
+            ),
+            inner: signal,
+        }
+    }
+}
+
+/// Band configuration that can be updated from another thread
+struct Band {
+    options: SharedOptions,
+    enabled: AtomicBool,
+}
+
+/// Audio-thread state of a band
+struct BandState {
+    smoother: Smoother,
+    /// Proportion of the filtered signal in the output, for crossfading when enabled or disabled
+    mix: Smoothed<f32>,
+    state: Box<[[f32; 2]]>,
+}
+
+impl<T: Signal + ?Sized> Signal for ParametricEq<T>
+where
+    T::Frame: Frame,
+{
+    type Frame = T::Frame;
+
+    #[allow(clippy::float_cmp)]
+    fn sample(&self, interval: f32, out: &mut [T::Frame]) {
+        self.inner.sample(interval, out);
+        let mut state = self.state.borrow_mut();
+        for (band, state) in self.bands.iter().zip(state.iter_mut()) {
+            state.smoother.set(band.options.received());
+            let mix = if band.enabled.load(Ordering::Relaxed) {
+                1.0
+            } else {
+                0.0
+            };
+            if state.mix.get() != mix {
+                state.mix.set(mix);
+            }
+        }
+
+        for chunk in out.chunks_mut(CHUNK_SIZE) {
+            for state in state.iter_mut() {
+                let coefficients = state.smoother.coefficients(interval);
+                for x in chunk.iter_mut() {
+                    let y = coefficients.process(&mut state.state, x);
+                    *x = frame::lerp(x, &y, state.mix.get());
+                    state.mix.advance(interval / SMOOTHING_PERIOD);
+                }
+                state.smoother.advance(interval * chunk.len() as f32);
+            }
+        }
+    }
+
+    fn remaining(&self) -> f32 {
+        self.inner.remaining()
+    }
+
+    #[inline]
+    fn handle_dropped(&self) {
+        self.inner.handle_dropped();
+    }
+}
+
+impl<T: ?Sized> Filter for ParametricEq<T> {
+    type Inner = T;
+    fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+/// Thread-safe control for a [`ParametricEq`] filter
+pub struct ParametricEqControl<'a>(&'a [Band]);
+
+unsafe impl<'a, T: 'a> Controlled<'a> for ParametricEq<T> {
+    type Control = ParametricEqControl<'a>;
+
+    unsafe fn make_control(signal: &'a ParametricEq<T>) -> Self::Control {
+        ParametricEqControl(&signal.bands)
+    }
+}
+
+impl<'a> ParametricEqControl<'a> {
+    /// Number of bands
+    pub fn bands(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Get the configuration most recently set for `band`
+    pub fn band(&self, band: usize) -> BiquadOptions {
+        self.0[band].options.load()
+    }
+
+    /// Replace the configuration of `band`
+    pub fn set_band(&mut self, band: usize, options: BiquadOptions) {
+        self.0[band].options.store(options);
+    }
+
+    /// Whether `band` is enabled
+    pub fn is_enabled(&self, band: usize) -> bool {
+        self.0[band].enabled.load(Ordering::Relaxed)
+    }
+
+    /// Enable or disable `band`
+    pub fn set_enabled(&mut self, band: usize, enabled: bool) {
+        self.0[band].enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Compute the amplitude ratio that the enabled bands, as most recently configured, together
+    /// apply to a sinusoid at `frequency_hz`, when sampled at `sample_rate` Hz
+    ///
+    /// Evaluate at logarithmically spaced frequencies to draw an EQ curve.
+    pub fn magnitude(&self, frequency_hz: f32, sample_rate: u32) -> f32 {
+        let sample_rate = sample_rate as f32;
+        self.0
+            .iter()
+            .filter(|band| band.enabled.load(Ordering::Relaxed))
+            .map(|band| {
+                Coefficients::new(&band.options.load(), sample_rate)
+                    .magnitude(frequency_hz, sample_rate)
+            })
+            .product()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BiquadKind, Sine};
+
+    const BANDS: [BiquadOptions; 2] = [
+        BiquadOptions {
+            kind: BiquadKind::Peaking,
+            frequency: 1000.0,
+            q: 2.0,
+            gain: 6.0,
+        },
+        BiquadOptions {
+            kind: BiquadKind::HighShelf,
+            frequency: 8000.0,
+            q: 1.0,
+            gain: -12.0,
+        },
+    ];
+
+    #[test]
+    fn response() {
+        let eq = ParametricEq::new(Sine::new(0.0, 1000.0), &BANDS);
+        let mut control = unsafe { ParametricEq::make_control(&eq) };
+        let db = |x: f32| 20.0 * x.log10();
+        assert!((db(control.magnitude(1000.0, 48_000)) - 6.0).abs() < 0.1);
+        assert!((db(control.magnitude(20_000.0, 48_000)) + 12.0).abs() < 0.5);
+        control.set_enabled(1, false);
+        assert!(db(control.magnitude(20_000.0, 48_000)).abs() < 0.1);
+    }
+
+    #[test]
+    fn disable() {
+        let eq = ParametricEq::new(Sine::new(0.0, 1000.0), &BANDS[..1]);
+        let mut control = unsafe { ParametricEq::make_control(&eq) };
+        let mut buf = [0.0; 4800];
+        let peak = |buf: &[f32]| buf[2400..].iter().fold(0.0f32, |acc, x| acc.max(x.abs()));
+        eq.sample(1.0 / 48_000.0, &mut buf);
+        assert!((peak(&buf) - 10.0f32.powf(6.0 / 20.0)).abs() < 1e-2);
+        control.set_enabled(0, false);
+        eq.sample(1.0 / 48_000.0, &mut buf);
+        assert!((peak(&buf) - 1.0).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,125 @@
+use core::cell::RefCell;
+
+use crate::spsc;
+
+/// Bounded SPSC queue of timestamped events
+///
+/// Unlike [`Swap`](crate::Swap), which only retains the most recent value, every event sent is
+/// delivered, in the order sent. Useful for custom controllable signals that must react to
+/// sequences of commands, like note on and note off, issued between calls to
+/// [`Signal::sample`](crate::Signal::sample).
+///
+/// Sending and receiving never block or allocate. If the queue is full, [`send`](Self::send)
+/// rejects the new event, so that events already queued are never lost.
+///
+/// # Example
+/// ```
+/// # use core::cell::Cell;
+/// # use oddio::*;
+/// /// Outputs 1 while a note is held
+/// struct Gate {
+///     notes: Events<bool>,
+///     /// Seconds since construction
+///     time: Cell<f64>,
+///     on: Cell<bool>,
+/// }
+///
+/// impl Signal for Gate {
+///     type Frame = Sample;
+///     fn sample(&self, interval: f32, out: &mut [Sample]) {
+///         self.notes.refresh();
+///         for o in out {
+///             while let Some((_, on)) = self.notes.pop(self.time.get()) {
+///                 self.on.set(on);
+///             }
+///             *o = if self.on.get() { 1.0 } else { 0.0 };
+///             self.time.set(self.time.get() + f64::from(interval));
+///         }
+///     }
+/// }
+///
+/// pub struct GateControl<'a>(&'a Events<bool>);
+///
+/// unsafe impl<'a> Controlled<'a> for Gate {
+///     type Control = GateControl<'a>;
+///     unsafe fn make_control(signal: &'a Gate) -> GateControl<'a> {
+///         GateControl(&signal.notes)
+///     }
+/// }
+/// ```
+pub struct Events<T> {
+    send: RefCell<spsc::Sender<(f64, T)>>,
+    recv: RefCell<spsc::Receiver<(f64, T)>>,
+}
+
+impl<T> Events<T> {
+    /// Create a queue that can hold up to `capacity` undelivered events
+    pub fn new(capacity: usize) -> Self {
+        let (send, recv) = spsc::channel(capacity);
+        Self {
+            send: RefCell::new(send),
+            recv: RefCell::new(recv),
+        }
+    }
+
+    /// Maximum number of undelivered events
+    pub fn capacity(&self) -> usize {
+        self.send.borrow().capacity()
+    }
+
+    /// Enqueue `value` for delivery at `time`. Producer only.
+    ///
+    /// `time` is interpreted by the consumer, typically as seconds on the signal's own clock.
+    /// Events are delivered in the order sent, so should be sent in order of time. Returns `value`
+    /// if the queue is full.
+    pub fn send(&self, time: f64, value: T) -> Result<(), T> {
+        self.send
+            .borrow_mut()
+            .send((time, value), 0)
+            .map_err(|(_, value)| value)
+    }
+
+    /// Make recently sent events available to [`pop`](Self::pop). Consumer only.
+    pub fn refresh(&self) {
+        self.recv.borrow_mut().update();
+    }
+
+    /// Take the next event, if it's due at or before `time`. Consumer only.
+    ///
+    /// Returns the event's timestamp along with its value. Events sent since the last call to
+    /// [`refresh`](Self::refresh) are not visible.
+    pub fn pop(&self, time: f64) -> Option<(f64, T)> {
+        let mut recv = self.recv.borrow_mut();
+        if recv.len() == 0 || recv[0].0 > time {
+            return None;
+        }
+        recv.pop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delivery() {
+        let events = Events::new(3);
+        assert_eq!(events.capacity(), 3);
+        for (i, t) in [0.0, 0.5, 0.5].iter().enumerate() {
+            events.send(*t, i).unwrap();
+        }
+        assert_eq!(events.send(1.0, 3), Err(3));
+        assert_eq!(events.pop(1.0), None);
+
+        events.refresh();
+        assert_eq!(events.pop(0.25), Some((0.0, 0)));
+        assert_eq!(events.pop(0.25), None);
+        assert_eq!(events.pop(0.5), Some((0.5, 1)));
+        assert_eq!(events.pop(0.5), Some((0.5, 2)));
+        assert_eq!(events.pop(f64::INFINITY), None);
+
+        events.send(1.0, 3).unwrap();
+        events.refresh();
+        assert_eq!(events.pop(1.0), Some((1.0, 3)));
+    }
+}
@@ -0,0 +1,148 @@
+//! Fast Fourier transform support
+
+use alloc::boxed::Box;
+use core::{
+    f64::consts::TAU,
+    ops::{Add, Mul, Sub},
+};
+
+use crate::math::Float;
+
+/// A complex number
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub(crate) struct Complex {
+    pub(crate) re: f32,
+    pub(crate) im: f32,
+}
+
+impl Complex {
+    pub(crate) const ZERO: Self = Self { re: 0.0, im: 0.0 };
+
+    pub(crate) fn new(re: f32, im: f32) -> Self {
+        Self { re, im }
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+/// Precomputed state for transforming sequences of a fixed power-of-two length
+///
+/// Transforms don't allocate, so can be performed on the audio thread.
+pub(crate) struct Fft {
+    /// `e^(-2πik/n)` for k in 0..n/2
+    twiddles: Box<[Complex]>,
+}
+
+impl Fft {
+    /// Prepare to transform sequences of length `n`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is not a power of two.
+    pub(crate) fn new(n: usize) -> Self {
+        assert!(n.is_power_of_two(), "FFT length must be a power of two");
+        Self {
+            twiddles: (0..n / 2)
+                .map(|k| {
+                    let angle = -TAU * k as f64 / n as f64;
+                    Complex::new(angle.cos() as f32, angle.sin() as f32)
+                })
+                .collect(),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.twiddles.len() * 2
+    }
+
+    /// Replace `data` with its discrete Fourier transform
+    pub(crate) fn forward(&self, data: &mut [Complex]) {
+        self.transform(data, false);
+    }
+
+    /// Replace `data` with its inverse discrete Fourier transform, scaled by the length
+    pub(crate) fn inverse(&self, data: &mut [Complex]) {
+        self.transform(data, true);
+    }
+
+    fn transform(&self, data: &mut [Complex], inverse: bool) {
+        let n = self.len();
+        assert_eq!(data.len(), n);
+        if n < 2 {
+            return;
+        }
+
+        // Bit-reversal permutation
+        let bits = n.trailing_zeros();
+        for i in 0..n {
+            let j = i.reverse_bits() >> (usize::BITS - bits);
+            if i < j {
+                data.swap(i, j);
+            }
+        }
+
+        // Iterative radix-2 butterflies
+        let mut size = 2;
+        while size <= n {
+            let half = size / 2;
+            let stride = n / size;
+            for start in (0..n).step_by(size) {
+                for k in 0..half {
+                    let mut w = self.twiddles[k * stride];
+                    if inverse {
+                        w.im = -w.im;
+                    }
+                    let a = data[start + k];
+                    let b = data[start + k + half] * w;
+                    data[start + k] = a + b;
+                    data[start + k + half] = a - b;
+                }
+            }
+            size *= 2;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let fft = Fft::new(8);
+        let input = (0..8)
+            .map(|i| Complex::new(i as f32, 0.0))
+            .collect::<alloc::vec::Vec<_>>();
+        let mut data = input.clone();
+        fft.forward(&mut data);
+        assert_eq!(data[0], Complex::new(28.0, 0.0));
+        assert!((data[4].re + 4.0).abs() < 1e-5);
+        fft.inverse(&mut data);
+        for (x, y) in data.iter().zip(&input) {
+            assert!((x.re / 8.0 - y.re).abs() < 1e-5);
+            assert!((x.im / 8.0).abs() < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,348 @@
+use core::{
+    cell::Cell,
+    f32::consts::TAU,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use crate::{
+    envelope::{Adsr, Gate},
+    math::Float,
+    Controlled, EnvelopeOptions, Sample, Seek, Signal,
+};
+
+const MAX_OPERATORS: usize = Fm::MAX_OPERATORS;
+
+/// A [`Signal`] that synthesizes sound by frequency (phase) modulation of sine operators
+///
+/// Each operator is a sine oscillator with its own frequency ratio, level and envelope. The
+/// [`FmAlgorithm`] determines which operators modulate the phase of which others, and which are
+/// heard directly. Even two operators can produce a wide range of bells, basses, and metallic or
+/// sci-fi effects.
+///
+/// As with [`Envelope`](crate::Envelope), the gate starts open, and the voice finishes once every
+/// operator's envelope has been released.
+pub struct Fm {
+    operators: [FmOperator; MAX_OPERATORS],
+    algorithm: FmAlgorithm,
+    count: usize,
+    /// Cycles per second of an operator with a ratio of 1
+    frequency: AtomicU32,
+    gate: Gate,
+    /// Fraction of a cycle of each operator
+    phases: Cell<[f32; MAX_OPERATORS]>,
+    envelopes: Cell<[Adsr; MAX_OPERATORS]>,
+}
+
+impl Fm {
+    /// Maximum number of operators in a voice
+    pub const MAX_OPERATORS: usize = 6;
+
+    /// Construct a voice at `frequency_hz` from `operators`, connected according to `algorithm`
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are fewer than 2 or more than [`MAX_OPERATORS`](Self::MAX_OPERATORS)
+    /// operators, or if `algorithm` refers to operators that don't exist.
+    pub fn new(frequency_hz: f32, operators: &[FmOperator], algorithm: FmAlgorithm) -> Self {
+        let count = operators.len();
+        assert!(
+            (2..=MAX_OPERATORS).contains(&count),
+            "FM voices must have between 2 and {} operators",
+            MAX_OPERATORS
+        );
+        let unused = !0u8 << count;
+        assert!(
+            algorithm.carriers & unused == 0
+                && algorithm.modulators[count..].iter().all(|&x| x == 0)
+                && algorithm.modulators.iter().all(|&x| x & unused == 0),
+            "algorithm refers to nonexistent operators"
+        );
+
+        let mut padded = [FmOperator::default(); MAX_OPERATORS];
+        padded[..count].copy_from_slice(operators);
+        let mut envelopes = [Adsr::new(); MAX_OPERATORS];
+        for (envelope, operator) in envelopes.iter_mut().zip(&padded[..count]) {
+            envelope.gate_on(&operator.envelope);
+        }
+        Self {
+            operators: padded,
+            algorithm,
+            count,
+            frequency: AtomicU32::new(frequency_hz.to_bits()),
+            gate: Gate::new(),
+            phases: Cell::new([0.0; MAX_OPERATORS]),
+            envelopes: Cell::new(envelopes),
+        }
+    }
+
+    fn frequency(&self) -> f32 {
+        f32::from_bits(self.frequency.load(Ordering::Relaxed))
+    }
+
+    fn advance(&self, seconds: f32) {
+        let frequency = self.frequency();
+        let mut phases = self.phases.get();
+        let mut envelopes = self.envelopes.get();
+        for ((phase, envelope), operator) in phases
+            .iter_mut()
+            .zip(envelopes.iter_mut())
+            .zip(&self.operators[..self.count])
+        {
+            *phase = (*phase + seconds * frequency * operator.ratio).rem_euclid(1.0);
+            envelope.advance(&operator.envelope, seconds);
+        }
+        self.phases.set(phases);
+        self.envelopes.set(envelopes);
+    }
+}
+
+/// Configuration for one operator of an [`Fm`] voice
+#[derive(Debug, Copy, Clone)]
+pub struct FmOperator {
+    /// Frequency relative to that of the voice. Integer ratios produce harmonic tones, while
+    /// others produce inharmonic, bell-like tones.
+    pub ratio: f32,
+    /// Peak amplitude of a carrier, or peak modulation index, in radians, of a modulator
+    pub level: f32,
+    /// Shape of the operator's level over time
+    pub envelope: EnvelopeOptions,
+}
+
+impl Default for FmOperator {
+    fn default() -> Self {
+        Self {
+            ratio: 1.0,
+            level: 1.0,
+            envelope: EnvelopeOptions::default(),
+        }
+    }
+}
+
+/// Connections between the operators of an [`Fm`] voice
+///
+/// Operators are identified by their index. To ensure that modulation doesn't form cycles, an
+/// operator can only be modulated by operators with higher indices.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FmAlgorithm {
+    /// Bitmask of operators whose output is heard
+    carriers: u8,
+    /// Bitmask of operators modulating each operator
+    modulators: [u8; MAX_OPERATORS],
+}
+
+impl FmAlgorithm {
+    /// An algorithm in which only the operators in `carriers` are heard and nothing is modulated
+    pub fn new(carriers: &[usize]) -> Self {
+        let mut result = Self {
+            carriers: 0,
+            modulators: [0; MAX_OPERATORS],
+        };
+        for &carrier in carriers {
+            assert!(carrier < MAX_OPERATORS, "no such operator");
+            result.carriers |= 1 << carrier;
+        }
+        result
+    }
+
+    /// Use the output of operator `modulator` to modulate the phase of operator `carrier`
+    ///
+    /// # Panics
+    ///
+    /// Panics unless `carrier < modulator < Fm::MAX_OPERATORS`.
+    pub fn modulate(mut self, modulator: usize, carrier: usize) -> Self {
+        assert!(
+            carrier < modulator && modulator < MAX_OPERATORS,
+            "operators can only be modulated by operators with higher indices"
+        );
+        self.modulators[carrier] |= 1 << modulator;
+        self
+    }
+
+    /// Operator 0 is heard, modulated by operator 1, modulated by operator 2, and so on up to
+    /// operator `count - 1`
+    pub fn stack(count: usize) -> Self {
+        (1..count).fold(Self::new(&[0]), |acc, i| acc.modulate(i, i - 1))
+    }
+
+    /// Operators 0 to `count - 1` are all heard, without modulation
+    pub fn parallel(count: usize) -> Self {
+        Self::new(&(0..count).collect::<alloc::vec::Vec<_>>())
+    }
+
+    /// Pairs of operators, where each even-numbered operator is heard, modulated by the next
+    pub fn pairs(count: usize) -> Self {
+        let carriers = (0..count).step_by(2).collect::<alloc::vec::Vec<_>>();
+        (0..count / 2).fold(Self::new(&carriers), |acc, i| {
+            acc.modulate(2 * i + 1, 2 * i)
+        })
+    }
+}
+
+impl Signal for Fm {
+    type Frame = Sample;
+
+    fn sample(&self, interval: f32, out: &mut [Sample]) {
+        let events = self.gate.poll();
+        let mut envelopes = self.envelopes.get();
+        for (envelope, operator) in envelopes.iter_mut().zip(&self.operators[..self.count]) {
+            envelope.update(events, &operator.envelope);
+        }
+        self.envelopes.set(envelopes);
+
+        let frequency = self.frequency();
+        let mut phases = self.phases.get();
+        let operators = &self.operators[..self.count];
+        for x in out {
+            let mut outputs = [0.0; MAX_OPERATORS];
+            // Higher-numbered operators modulate lower-numbered ones, so evaluate in reverse
+            for i in (0..self.count).rev() {
+                let modulators = self.algorithm.modulators[i];
+                let modulation = (i + 1..self.count)
+                    .filter(|j| modulators & (1 << j) != 0)
+                    .map(|j| outputs[j])
+                    .sum::<f32>();
+                let level = operators[i].level * envelopes[i].level(&operators[i].envelope);
+                outputs[i] = level * (TAU * phases[i] + modulation).sin();
+            }
+            *x = (0..self.count)
+                .filter(|i| self.algorithm.carriers & (1 << i) != 0)
+                .map(|i| outputs[i])
+                .sum();
+
+            for (i, operator) in operators.iter().enumerate() {
+                phases[i] = (phases[i] + interval * frequency * operator.ratio).rem_euclid(1.0);
+                envelopes[i].advance(&operator.envelope, interval);
+            }
+        }
+        self.phases.set(phases);
+        self.envelopes.set(envelopes);
+    }
+
+    fn remaining(&self) -> f32 {
+        // Account for gate changes that haven't been sampled yet
+        let events = self.gate.peek();
+        self.envelopes.get()[..self.count]
+            .iter()
+            .zip(&self.operators)
+            .map(|(&envelope, operator)| {
+                let mut envelope = envelope;
+                envelope.update(events, &operator.envelope);
+                envelope.remaining(&operator.envelope)
+            })
+            .fold(0.0, f32::max)
+    }
+}
+
+impl Seek for Fm {
+    fn seek(&self, seconds: f32) {
+        self.advance(seconds);
+    }
+}
+
+/// Thread-safe control for an [`Fm`] voice
+pub struct FmControl<'a> {
+    frequency: &'a AtomicU32,
+    gate: &'a Gate,
+}
+
+unsafe impl<'a> Controlled<'a> for Fm {
+    type Control = FmControl<'a>;
+
+    unsafe fn make_control(signal: &'a Fm) -> Self::Control {
+        FmControl {
+            frequency: &signal.frequency,
+            gate: &signal.gate,
+        }
+    }
+}
+
+impl<'a> FmControl<'a> {
+    /// Get the current frequency in Hz
+    pub fn frequency(&self) -> f32 {
+        f32::from_bits(self.frequency.load(Ordering::Relaxed))
+    }
+
+    /// Set the frequency in Hz of operators with a ratio of 1
+    pub fn set_frequency(&mut self, frequency_hz: f32) {
+        self.frequency
+            .store(frequency_hz.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Open the gate, restarting every operator's attack from its current level
+    pub fn gate_on(&mut self) {
+        self.gate.open();
+    }
+
+    /// Close the gate, releasing every operator
+    pub fn gate_off(&mut self) {
+        self.gate.close();
+    }
+
+    /// Whether the gate is open
+    pub fn is_gate_on(&self) -> bool {
+        self.gate.is_open()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Curve, Sine};
+
+    const ORGAN: EnvelopeOptions = EnvelopeOptions {
+        attack: 0.0,
+        decay: 0.0,
+        sustain: 1.0,
+        release: 0.5,
+        curve: Curve::Linear,
+    };
+
+    #[test]
+    fn unmodulated() {
+        let operators = [
+            FmOperator {
+                ratio: 2.0,
+                level: 1.0,
+                envelope: ORGAN,
+            },
+            FmOperator {
+                ratio: 3.0,
+                level: 0.0,
+                envelope: ORGAN,
+            },
+        ];
+        let fm = Fm::new(100.0, &operators, FmAlgorithm::stack(2));
+        let sine = Sine::new(0.0, 200.0);
+        let mut expected = [0.0; 64];
+        let mut actual = [0.0; 64];
+        sine.sample(1e-4, &mut expected);
+        fm.sample(1e-4, &mut actual);
+        for (x, y) in actual.iter().zip(&expected) {
+            assert!((x - y).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn release() {
+        let operators = [FmOperator {
+            envelope: ORGAN,
+            ..FmOperator::default()
+        }; 3];
+        let fm = Fm::new(440.0, &operators, FmAlgorithm::pairs(3));
+        let mut control = unsafe { Fm::make_control(&fm) };
+        let mut buf = [0.0; 10];
+        fm.sample(0.01, &mut buf);
+        assert_eq!(fm.remaining(), f32::INFINITY);
+        control.gate_off();
+        fm.sample(0.1, &mut buf);
+        assert!(fm.remaining() <= 0.0);
+        assert!(buf[9].abs() < 1e-6);
+
+        // Voices with no carriers are silent, but still follow the gate
+        let fm = Fm::new(440.0, &operators[..2], FmAlgorithm::new(&[]));
+        let mut control = unsafe { Fm::make_control(&fm) };
+        assert_eq!(fm.remaining(), f32::INFINITY);
+        control.gate_off();
+        assert_eq!(fm.remaining(), ORGAN.release);
+    }
+}
@@ -3,7 +3,10 @@ use core::{
     sync::atomic::{AtomicU32, Ordering},
 };
 
-use crate::{frame, math::Float, Controlled, Filter, Frame, Seek, Signal, Smoothed};
+use crate::{
+    automation::Automated, frame, math::Float, swap::Swap, Automation, Controlled, Filter, Frame,
+    Seek, Signal, Smoothed,
+};
 
 /// Amplifies a signal by a constant amount
 ///
@@ -65,6 +68,10 @@ where
 pub struct Gain<T: ?Sized> {
     shared: AtomicU32,
     gain: RefCell<Smoothed<f32>>,
+    /// Automation of the gain in decibels, or `None` to follow `shared`
+    automation: Swap<Option<Automation>>,
+    /// Automation in progress, and the sign of the gain when it began
+    automated: RefCell<Option<(Automated, f32)>>,
     inner: T,
 }
 
@@ -74,6 +81,8 @@ impl<T> Gain<T> {
         Self {
             shared: AtomicU32::new(1.0f32.to_bits()),
             gain: RefCell::new(Smoothed::new(1.0)),
+            automation: Swap::new(None),
+            automated: RefCell::new(None),
             inner: signal,
         }
     }
@@ -88,8 +97,25 @@ where
     #[allow(clippy::float_cmp)]
     fn sample(&self, interval: f32, out: &mut [T::Frame]) {
         self.inner.sample(interval, out);
-        let shared = f32::from_bits(self.shared.load(Ordering::Relaxed));
         let mut gain = self.gain.borrow_mut();
+        let mut automated = self.automated.borrow_mut();
+        if self.automation.refresh() {
+            // Decibels can't express polarity, so automation preserves the gain's sign
+            let start = 20.0 * gain.get().abs().max(MIN_RATIO).log10();
+            let sign = if gain.get() < 0.0 { -1.0 } else { 1.0 };
+            *automated =
+                unsafe { *self.automation.received() }.map(|x| (Automated::new(x, start), sign));
+        }
+        if let Some((ref mut automated, sign)) = *automated {
+            for x in out {
+                *x = frame::scale(x, sign * 10.0f32.powf(automated.get() / 20.0));
+                automated.advance(interval);
+            }
+            *gain = Smoothed::new(sign * 10.0f32.powf(automated.get() / 20.0));
+            return;
+        }
+
+        let shared = f32::from_bits(self.shared.load(Ordering::Relaxed));
         if gain.get() != shared {
             gain.set(shared);
         }
@@ -117,18 +143,26 @@ impl<T> Filter for Gain<T> {
 }
 
 /// Thread-safe control for a [`Gain`] filter
-pub struct GainControl<'a>(&'a AtomicU32);
+pub struct GainControl<'a> {
+    shared: &'a AtomicU32,
+    automation: &'a Swap<Option<Automation>>,
+}
 
 unsafe impl<'a, T: 'a> Controlled<'a> for Gain<T> {
     type Control = GainControl<'a>;
 
     unsafe fn make_control(signal: &'a Gain<T>) -> Self::Control {
-        GainControl(&signal.shared)
+        GainControl {
+            shared: &signal.shared,
+            automation: &signal.automation,
+        }
     }
 }
 
 impl<'a> GainControl<'a> {
-    /// Get the current amplification in decibels
+    /// Get the amplification most recently set, in decibels
+    ///
+    /// Doesn't reflect automation.
     pub fn gain(&self) -> f32 {
         20.0 * self.amplitude_ratio().log10()
     }
@@ -142,9 +176,11 @@ impl<'a> GainControl<'a> {
         self.set_amplitude_ratio(10.0f32.powf(db / 20.0));
     }
 
-    /// Get the current amplitude scaling factor
+    /// Get the amplitude scaling factor most recently set
+    ///
+    /// Doesn't reflect automation.
     pub fn amplitude_ratio(&self) -> f32 {
-        f32::from_bits(self.0.load(Ordering::Relaxed))
+        f32::from_bits(self.shared.load(Ordering::Relaxed))
     }
 
     /// Scale the amplitude of the signal directly
@@ -152,27 +188,80 @@ impl<'a> GainControl<'a> {
     /// This is nonlinear in terms of both perception and power. Most users should prefer
     /// `set_gain`. Unlike `set_gain`, this method allows a signal to be completely zeroed out if
     /// needed, or even have its phase inverted with a negative factor.
+    ///
+    /// Cancels automation in progress.
     pub fn set_amplitude_ratio(&mut self, factor: f32) {
-        self.0.store(factor.to_bits(), Ordering::Relaxed);
+        self.shared.store(factor.to_bits(), Ordering::Relaxed);
+        self.send(None);
+    }
+
+    /// Drive the gain with `automation`, in decibels, replacing any automation in progress
+    ///
+    /// The gain holds the final value of the automation until it's changed again. See
+    /// [`Automation`] for details.
+    pub fn automate(&mut self, automation: Automation) {
+        self.send(Some(automation));
+    }
+
+    fn send(&mut self, automation: Option<Automation>) {
+        unsafe {
+            *self.automation.pending() = automation;
+        }
+        self.automation.flush();
     }
 }
 
 /// Number of seconds over which to smooth a change in gain
 const SMOOTHING_PERIOD: f32 = 0.1;
 
+/// Amplitude ratio treated as silence when beginning automation, to keep decibels finite
+const MIN_RATIO: f32 = 1e-5;
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::Constant;
+    use crate::{Constant, Curve};
 
     #[test]
     fn smoothing() {
         let s = Gain::new(Constant(1.0));
         let mut buf = [0.0; 6];
-        GainControl(&s.shared).set_amplitude_ratio(5.0);
+        let mut control = unsafe { Gain::make_control(&s) };
+        control.set_amplitude_ratio(5.0);
         s.sample(0.025, &mut buf);
         assert_eq!(buf, [1.0, 2.0, 3.0, 4.0, 5.0, 5.0]);
         s.sample(0.025, &mut buf);
         assert_eq!(buf, [5.0; 6]);
     }
+
+    #[test]
+    fn automation() {
+        let s = Gain::new(Constant(1.0));
+        let mut control = unsafe { Gain::make_control(&s) };
+        control.automate(Automation::new().then(0.5, -20.0, Curve::Linear).then(
+            1.0,
+            -40.0,
+            Curve::Linear,
+        ));
+        let mut buf = [0.0; 6];
+        s.sample(0.25, &mut buf);
+        let expected = [1.0, 10f32.powf(-0.5), 0.1, 10f32.powf(-1.5), 0.01, 0.01];
+        for (x, y) in buf.iter().zip(&expected) {
+            assert!((x - y).abs() < 1e-6, "{} vs {}", x, y);
+        }
+
+        // Setting the gain cancels automation, smoothly
+        control.set_gain(0.0);
+        s.sample(0.025, &mut buf);
+        assert!((buf[0] - 0.01).abs() < 1e-6);
+        assert_eq!(buf[5], 1.0);
+
+        // Automation preserves inverted polarity
+        control.set_amplitude_ratio(-1.0);
+        s.sample(0.025, &mut buf);
+        control.automate(Automation::new().then(0.5, -20.0, Curve::Linear));
+        s.sample(0.25, &mut buf);
+        assert_eq!(buf[0], -1.0);
+        assert!((buf[2] + 0.1).abs() < 1e-6);
+    }
 }
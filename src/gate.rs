@@ -0,0 +1,317 @@
+use core::{
+    cell::Cell,
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+};
+
+use crate::{frame, math::Float, swap::Swap, Controlled, Filter, Frame, Signal};
+
+/// Silences a signal while it's quiet, e.g. to cut background hiss from a microphone between
+/// phrases
+///
+/// The gate opens when the detected level rises above the threshold, and closes once it's fallen
+/// below the threshold by the hysteresis and then stayed there for the hold time. While closed, the
+/// signal is attenuated by the range, or with a finite ratio, expanded downwards in proportion to
+/// how far it's fallen below the threshold.
+///
+/// By default the level of the signal itself is detected. Use [`with_key`](Self::with_key) to
+/// detect the level of a separate sidechain signal instead. Unlike [`Duck`](crate::Duck)'s key,
+/// the sidechain only drives the detector, and isn't heard.
+pub struct Gate<T: ?Sized, K = ()> {
+    options: Swap<GateOptions>,
+    state: Cell<State>,
+    /// Most recent gain reduction in decibels, for reporting
+    reduction: AtomicU32,
+    open: AtomicBool,
+    key: K,
+    inner: T,
+}
+
+#[derive(Copy, Clone)]
+struct State {
+    open: bool,
+    /// Seconds remaining until the gate closes, once the level has fallen
+    hold: f32,
+    /// Current gain in decibels
+    gain: f32,
+}
+
+impl<T> Gate<T> {
+    /// Gate `signal` by its own level
+    pub fn new(signal: T, options: GateOptions) -> Self {
+        Gate::with_key((), signal, options)
+    }
+}
+
+impl<T, K> Gate<T, K> {
+    /// Gate `signal` by the level of `key`
+    pub fn with_key(key: K, signal: T, options: GateOptions) -> Self {
+        Self {
+            options: Swap::new(options),
+            state: Cell::new(State {
+                open: false,
+                hold: 0.0,
+                gain: options.range,
+            }),
+            reduction: AtomicU32::new((-options.range).to_bits()),
+            open: AtomicBool::new(false),
+            key,
+            inner: signal,
+        }
+    }
+}
+
+impl<T, K> Gate<T, K>
+where
+    T: Signal + ?Sized,
+    T::Frame: Frame + Copy,
+{
+    /// Sample the inner signal into `out`, gated by the level of the key that `detect` writes into
+    /// its second argument for each chunk of the inner signal, passed as its first
+    fn sample_gated(
+        &self,
+        interval: f32,
+        out: &mut [T::Frame],
+        mut detect: impl FnMut(&[T::Frame], &mut [T::Frame]),
+    ) {
+        const CHUNK_SIZE: usize = 256;
+
+        let mut buf = [T::Frame::ZERO; CHUNK_SIZE];
+        for chunk in out.chunks_mut(CHUNK_SIZE) {
+            let buf = &mut buf[..chunk.len()];
+            self.inner.sample(interval, chunk);
+            detect(chunk, buf);
+            self.process(interval, buf, chunk);
+        }
+    }
+
+    /// Apply gain to `out` according to the level of each frame of `key`, passed alongside
+    fn process(&self, interval: f32, key: &[T::Frame], out: &mut [T::Frame]) {
+        self.options.refresh();
+        let options = unsafe { *self.options.received() };
+        let attack = 1.0 - (-interval / options.attack).exp();
+        let release = 1.0 - (-interval / options.release).exp();
+
+        let mut state = self.state.get();
+        for (k, o) in key.iter().zip(out) {
+            let peak = k.channels().iter().fold(0.0f32, |acc, &s| acc.max(s.abs()));
+            let level = 20.0 * peak.log10();
+            if level > options.threshold {
+                state.open = true;
+            }
+            if level >= options.threshold - options.hysteresis {
+                state.hold = options.hold;
+            } else if state.open {
+                state.hold -= interval;
+                if state.hold <= 0.0 {
+                    state.open = false;
+                }
+            }
+
+            let target = if state.open {
+                0.0
+            } else {
+                let below = options.threshold - level;
+                if below <= 0.0 {
+                    0.0
+                } else {
+                    (-below * (options.ratio - 1.0)).max(options.range)
+                }
+            };
+            let coeff = if target > state.gain { attack } else { release };
+            state.gain += coeff * (target - state.gain);
+            *o = frame::scale(o, 10.0f32.powf(state.gain / 20.0));
+        }
+        self.state.set(state);
+        self.reduction
+            .store((-state.gain).to_bits(), Ordering::Relaxed);
+        self.open.store(state.open, Ordering::Relaxed);
+    }
+}
+
+/// Configuration for a [`Gate`] filter
+#[derive(Debug, Copy, Clone)]
+pub struct GateOptions {
+    /// Level, in decibels, above which the gate opens
+    pub threshold: f32,
+    /// Decibels below the threshold to which the level must fall before the gate closes, to
+    /// prevent chattering when the level hovers near the threshold
+    pub hysteresis: f32,
+    /// Time constant, in seconds, with which the gain rises when the gate opens
+    pub attack: f32,
+    /// Seconds to remain open after the level falls
+    pub hold: f32,
+    /// Time constant, in seconds, with which the gain falls when the gate closes
+    pub release: f32,
+    /// Gain, in decibels, applied while the gate is closed. Should be negative.
+    pub range: f32,
+    /// Expansion ratio while closed: each decibel below the threshold becomes `ratio` decibels,
+    /// limited by the range. The default, infinity, makes a gate; finite values make a downward
+    /// expander.
+    pub ratio: f32,
+}
+
+impl Default for GateOptions {
+    fn default() -> Self {
+        Self {
+            threshold: -40.0,
+            hysteresis: 6.0,
+            attack: 0.001,
+            hold: 0.05,
+            release: 0.1,
+            range: -80.0,
+            ratio: f32::INFINITY,
+        }
+    }
+}
+
+impl<T> Signal for Gate<T>
+where
+    T: Signal + ?Sized,
+    T::Frame: Frame + Copy,
+{
+    type Frame = T::Frame;
+
+    fn sample(&self, interval: f32, out: &mut [T::Frame]) {
+        self.sample_gated(interval, out, |signal, key| key.copy_from_slice(signal));
+    }
+
+    fn remaining(&self) -> f32 {
+        self.inner.remaining()
+    }
+
+    #[inline]
+    fn handle_dropped(&self) {
+        self.inner.handle_dropped();
+    }
+}
+
+impl<T, K> Signal for Gate<T, K>
+where
+    K: Signal<Frame = T::Frame>,
+    T: Signal + ?Sized,
+    T::Frame: Frame + Copy,
+{
+    type Frame = T::Frame;
+
+    fn sample(&self, interval: f32, out: &mut [T::Frame]) {
+        self.sample_gated(interval, out, |_, key| self.key.sample(interval, key));
+    }
+
+    fn remaining(&self) -> f32 {
+        self.key.remaining().max(self.inner.remaining())
+    }
+
+    #[inline]
+    fn handle_dropped(&self) {
+        self.key.handle_dropped();
+        self.inner.handle_dropped();
+    }
+}
+
+impl<T: ?Sized, K> Filter for Gate<T, K> {
+    type Inner = T;
+    fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+/// Thread-safe control for a [`Gate`] filter
+pub struct GateControl<'a, K> {
+    options: &'a Swap<GateOptions>,
+    reduction: &'a AtomicU32,
+    open: &'a AtomicBool,
+    key: &'a K,
+}
+
+unsafe impl<'a, T: 'a, K: 'a> Controlled<'a> for Gate<T, K> {
+    type Control = GateControl<'a, K>;
+
+    unsafe fn make_control(signal: &'a Gate<T, K>) -> Self::Control {
+        GateControl {
+            options: &signal.options,
+            reduction: &signal.reduction,
+            open: &signal.open,
+            key: &signal.key,
+        }
+    }
+}
+
+impl<'a, K> GateControl<'a, K> {
+    /// Replace the filter's configuration
+    pub fn set_options(&mut self, options: GateOptions) {
+        unsafe {
+            *self.options.pending() = options;
+        }
+        self.options.flush();
+    }
+
+    /// Get the gain reduction most recently applied to the inner signal, in decibels
+    pub fn gain_reduction(&self) -> f32 {
+        f32::from_bits(self.reduction.load(Ordering::Relaxed))
+    }
+
+    /// Whether the gate was open at the end of the most recent block
+    pub fn is_open(&self) -> bool {
+        self.open.load(Ordering::Relaxed)
+    }
+
+    /// Get the control for the sidechain signal
+    pub fn key<'b>(&'b mut self) -> K::Control
+    where
+        K: Controlled<'b>,
+    {
+        unsafe { K::make_control(self.key) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Constant;
+
+    const OPTIONS: GateOptions = GateOptions {
+        threshold: -20.0,
+        hysteresis: 6.0,
+        attack: 1e-4,
+        hold: 0.5,
+        release: 1e-4,
+        range: -60.0,
+        ratio: f32::INFINITY,
+    };
+
+    #[test]
+    fn hold() {
+        let mut s = Gate::new(Constant(1.0), OPTIONS);
+        let mut buf = [0.0; 10];
+        s.sample(0.1, &mut buf);
+        assert!(buf.iter().all(|&x| (x - 1.0).abs() < 1e-6));
+
+        // Within the hysteresis, the gate stays open indefinitely
+        s.inner.0 = 0.08;
+        s.sample(0.1, &mut buf);
+        assert!(buf.iter().all(|&x| (x - 0.08).abs() < 1e-6));
+
+        // Below it, the gate stays open for the hold time
+        s.inner.0 = 0.01;
+        s.sample(0.1, &mut buf);
+        assert!(buf[..4].iter().all(|&x| (x - 0.01).abs() < 1e-6));
+        assert!(buf[6..].iter().all(|&x| (x - 1e-5).abs() < 1e-8));
+        let control = unsafe { Gate::make_control(&s) };
+        assert!(!control.is_open());
+        assert!((control.gain_reduction() - 60.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn expander() {
+        let options = GateOptions {
+            ratio: 2.0,
+            hold: 0.0,
+            ..OPTIONS
+        };
+        let s = Gate::with_key(Constant(0.01), Constant(1.0), options);
+        let mut buf = [0.0; 10];
+        s.sample(0.1, &mut buf);
+        // The key is 20 dB below the threshold, and isn't heard
+        assert!((buf[9] - 10.0f32.powf(-20.0 / 20.0)).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,226 @@
+use alloc::sync::Arc;
+use core::{cell::RefCell, f32::consts::TAU};
+
+use crate::{frame, math::Float, noise::Rng, swap::Swap, Controlled, Frame, Frames, Seek, Signal};
+
+/// Maximum number of simultaneously playing grains
+const MAX_GRAINS: usize = 64;
+
+/// A [`Signal`] that plays overlapping, windowed fragments ("grains") of [`Frames`], forever
+///
+/// Useful for stretching short recordings into endless, evolving textures. Each grain is a short
+/// excerpt of the source beginning near a configurable position, faded in and out with a Hann
+/// window. Randomizing the position and pitch of each grain hides the repetition.
+///
+/// Grains overlap by about `size * density` on average, and the output level grows accordingly. Up
+/// to 64 grains play at once; new grains are skipped while that many are playing.
+pub struct Granular<T> {
+    frames: Arc<Frames<T>>,
+    options: Swap<GranularOptions>,
+    state: RefCell<State>,
+}
+
+impl<T> Granular<T> {
+    /// Construct grains from `frames`
+    ///
+    /// Random choices are made by a generator initialized with `seed`, so output is reproducible.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `frames` is empty.
+    pub fn new(frames: Arc<Frames<T>>, options: GranularOptions, seed: u64) -> Self {
+        assert!(!frames.is_empty(), "grains require a nonempty source");
+        Self {
+            frames,
+            options: Swap::new(options),
+            state: RefCell::new(State {
+                grains: [Grain::default(); MAX_GRAINS],
+                until_next: 0.0,
+                rng: Rng::new(seed),
+            }),
+        }
+    }
+}
+
+impl<T: Frame> Granular<T> {
+    /// Interpolate a frame for position `sample`, wrapping around at the end
+    fn interpolate(&self, sample: f64) -> T {
+        let a = sample as usize;
+        let b = (a + 1) % self.frames.len();
+        frame::lerp(&self.frames[a], &self.frames[b], sample.fract() as f32)
+    }
+}
+
+/// Configuration for a [`Granular`] signal
+#[derive(Debug, Copy, Clone)]
+pub struct GranularOptions {
+    /// Duration of each grain, in seconds
+    pub size: f32,
+    /// Grains started per second, limited to one per sample
+    pub density: f32,
+    /// Seconds into the source at which grains begin, wrapping around at the end
+    pub position: f32,
+    /// Maximum random offset from `position` of each grain, in seconds
+    pub position_jitter: f32,
+    /// Playback rate of each grain, where 1 plays at the original pitch and speed
+    pub pitch: f32,
+    /// Maximum random deviation of each grain from `pitch`, in semitones
+    pub pitch_jitter: f32,
+}
+
+impl Default for GranularOptions {
+    fn default() -> Self {
+        Self {
+            size: 0.1,
+            density: 20.0,
+            position: 0.0,
+            position_jitter: 0.05,
+            pitch: 1.0,
+            pitch_jitter: 0.0,
+        }
+    }
+}
+
+struct State {
+    grains: [Grain; MAX_GRAINS],
+    /// Seconds until the next grain should start
+    until_next: f32,
+    rng: Rng,
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+struct Grain {
+    /// Position in the source, in samples
+    cursor: f64,
+    /// Samples of source advanced per second of output
+    rate: f64,
+    /// Seconds since the grain began
+    age: f32,
+    /// Total duration in seconds, or 0 if the grain is inactive
+    duration: f32,
+}
+
+impl Grain {
+    fn is_active(&self) -> bool {
+        self.age < self.duration
+    }
+}
+
+impl<T: Frame + Copy> Signal for Granular<T> {
+    type Frame = T;
+
+    fn sample(&self, interval: f32, out: &mut [T]) {
+        self.options.refresh();
+        let options = unsafe { *self.options.received() };
+        let state = &mut *self.state.borrow_mut();
+        let len = self.frames.len() as f64;
+        let source_rate = f64::from(self.frames.rate());
+
+        for o in out {
+            // Start a new grain, at most one per sample so that any density is bounded
+            state.until_next -= interval;
+            if state.until_next <= 0.0 {
+                state.until_next += (1.0 / options.density.max(1e-3)).max(interval);
+                if let Some(slot) = state.grains.iter_mut().find(|g| !g.is_active()) {
+                    let position = options.position + options.position_jitter * state.rng.bipolar();
+                    let semitones = options.pitch_jitter * state.rng.bipolar();
+                    let pitch = options.pitch * 2.0f32.powf(semitones / 12.0);
+                    *slot = Grain {
+                        cursor: (f64::from(position) * source_rate).rem_euclid(len),
+                        rate: f64::from(pitch) * source_rate,
+                        age: 0.0,
+                        duration: options.size,
+                    };
+                }
+            }
+
+            // Mix active grains
+            *o = T::ZERO;
+            for grain in state.grains.iter_mut().filter(|g| g.is_active()) {
+                let window = 0.5 - 0.5 * (TAU * grain.age / grain.duration).cos();
+                let x = self.interpolate(grain.cursor);
+                *o = frame::mix(o, &frame::scale(&x, window));
+                grain.cursor = (grain.cursor + grain.rate * f64::from(interval)).rem_euclid(len);
+                grain.age += interval;
+            }
+        }
+    }
+}
+
+impl<T: Frame + Copy> Seek for Granular<T> {
+    /// Advances grains in progress without producing output; new grains are not started
+    fn seek(&self, seconds: f32) {
+        let state = &mut *self.state.borrow_mut();
+        let len = self.frames.len() as f64;
+        for grain in state.grains.iter_mut().filter(|g| g.is_active()) {
+            grain.cursor = (grain.cursor + grain.rate * f64::from(seconds)).rem_euclid(len);
+            grain.age += seconds;
+        }
+        state.until_next = (state.until_next - seconds).max(0.0);
+    }
+}
+
+/// Thread-safe control for a [`Granular`] signal
+pub struct GranularControl<'a>(&'a Swap<GranularOptions>);
+
+unsafe impl<'a, T: 'a> Controlled<'a> for Granular<T> {
+    type Control = GranularControl<'a>;
+
+    unsafe fn make_control(signal: &'a Granular<T>) -> Self::Control {
+        GranularControl(&signal.options)
+    }
+}
+
+impl<'a> GranularControl<'a> {
+    /// Replace the configuration, affecting grains started from now on
+    pub fn set_options(&mut self, options: GranularOptions) {
+        unsafe {
+            *self.0.pending() = options;
+        }
+        self.0.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlap() {
+        // A constant source played through grains overlapping by exactly half sums to a constant
+        let frames = Frames::from_slice(100, &[1.0f32; 100]);
+        let options = GranularOptions {
+            size: 0.2,
+            density: 10.0,
+            ..GranularOptions::default()
+        };
+        let s = Granular::new(frames, options, 0);
+        let mut buf = [0.0; 100];
+        s.sample(0.01, &mut buf);
+        assert!(buf[0].abs() < 1e-6);
+        for x in &buf[20..] {
+            assert!((x - 1.0).abs() < 1e-3, "{}", x);
+        }
+
+        let mut control = unsafe { Granular::make_control(&s) };
+        control.set_options(GranularOptions {
+            density: 0.0,
+            ..options
+        });
+        s.sample(0.01, &mut buf);
+        assert!(buf[40..].iter().all(|&x| x == 0.0));
+
+        // Unbounded densities start one grain per sample
+        let s = Granular::new(
+            Frames::from_slice(100, &[1.0f32; 100]),
+            GranularOptions {
+                density: f32::INFINITY,
+                ..options
+            },
+            0,
+        );
+        s.sample(0.01, &mut buf[..10]);
+        let state = s.state.borrow();
+        assert_eq!(state.grains.iter().filter(|g| g.is_active()).count(), 10);
+    }
+}
@@ -41,17 +41,39 @@ extern crate alloc;
 extern crate std;
 
 mod adapt;
+mod automation;
+mod biquad;
+mod buses;
+mod compressor;
 mod constant;
+mod convolver;
 mod cycle;
+mod delay;
 mod downmix;
+mod duck;
+mod envelope;
+mod eq;
+mod events;
+mod fft;
 mod filter;
+mod fm;
 mod frame;
 mod frames;
 mod gain;
+mod gate;
+mod granular;
+mod limiter;
 mod math;
+mod meter;
 mod mixer;
+mod modulation;
+mod music;
+mod noise;
+mod oscillator;
 mod reinhard;
+mod reverb;
 mod ring;
+mod sequencer;
 mod set;
 mod signal;
 mod sine;
@@ -59,31 +81,62 @@ mod smooth;
 mod spatial;
 mod speed;
 mod spsc;
+mod stems;
 mod stop;
 mod stream;
 mod swap;
 mod tanh;
+mod wavetable;
+#[cfg(not(feature = "no_std"))]
+mod workers;
 
 pub use adapt::{Adapt, AdaptOptions};
+pub use automation::{Automation, Automator, AutomatorControl};
+pub use biquad::{Biquad, BiquadControl, BiquadKind, BiquadOptions};
+pub use buses::{Buses, BusesControl, Driven, Param, Snapshot};
+pub use compressor::{Compressor, CompressorControl, CompressorOptions};
 pub use constant::Constant;
+pub use convolver::{Convolver, ConvolverControl};
 pub use cycle::Cycle;
+pub use delay::{Delay, DelayControl, DelayOptions};
 pub use downmix::Downmix;
+pub use duck::{Duck, DuckControl, DuckOptions};
+pub use envelope::{Curve, Envelope, EnvelopeControl, EnvelopeOptions};
+pub use eq::{ParametricEq, ParametricEqControl};
+pub use events::Events;
 pub use filter::*;
+pub use fm::{Fm, FmAlgorithm, FmControl, FmOperator};
 pub use frame::Frame;
 pub use frames::*;
 pub use gain::{FixedGain, Gain, GainControl};
+pub use gate::{Gate, GateControl, GateOptions};
+pub use granular::{Granular, GranularControl, GranularOptions};
+pub use limiter::{Limiter, LimiterControl, LimiterOptions};
+pub use meter::{Meter, MeterControl};
 pub use mixer::*;
+pub use modulation::{
+    Chorus, ChorusControl, ChorusOptions, Flanger, FlangerControl, FlangerOptions, Phaser,
+    PhaserControl, PhaserOptions,
+};
+pub use music::{MusicPlayer, MusicPlayerControl, MusicTrack, Transition, TransitionPoint};
+pub use noise::{BrownNoise, PinkNoise, WhiteNoise};
+pub use oscillator::{FrequencyControl, Saw, Square, SquareControl, Triangle};
 pub use reinhard::Reinhard;
+pub use reverb::{Reverb, ReverbControl, ReverbOptions};
+pub use sequencer::{Sequencer, SequencerControl, SequencerOptions};
+pub use set::SignalId;
 use set::*;
 pub use signal::*;
 pub use sine::*;
 pub use smooth::{Interpolate, Smoothed};
 pub use spatial::*;
 pub use speed::{Speed, SpeedControl};
+pub use stems::{StemPlayer, StemPlayerControl};
 pub use stop::*;
 pub use stream::{Stream, StreamControl};
 pub use swap::Swap;
 pub use tanh::Tanh;
+pub use wavetable::{Wavetable, WavetableControl};
 
 /// Unitless instantaneous sound wave amplitude measurement
 pub type Sample = f32;
@@ -0,0 +1,333 @@
+use alloc::boxed::Box;
+use core::{
+    cell::RefCell,
+    f32::consts::PI,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use crate::{
+    compressor::MAX_SAMPLE_RATE, frame, math::Float, swap::Swap, Controlled, Filter, Frame, Signal,
+};
+
+/// Keeps a signal's true peak level below a ceiling, for protecting the output of a master bus
+///
+/// Peaks between samples, which can exceed every sample after conversion to analog, are estimated
+/// by 4x oversampling. The signal is delayed by [`LATENCY`](Self::LATENCY), plus a few samples, so
+/// that gain reduction can be ramped in smoothly ahead of each peak, ensuring that the ceiling is
+/// never exceeded by more than the error of the peak estimate. Gain then recovers according to the
+/// release time.
+pub struct Limiter<T: Signal + ?Sized> {
+    options: Swap<LimiterOptions>,
+    /// Most recent gain reduction in decibels, for reporting
+    reduction: AtomicU32,
+    state: RefCell<State<T::Frame>>,
+    inner: T,
+}
+
+struct State<F> {
+    /// Interpolation filter for each intermediate phase
+    phases: [[f32; TAPS]; OVERSAMPLING - 1],
+    /// Most recent input frames, oldest first
+    history: [F; TAPS],
+    /// Estimated peak between the two central frames of `history` on the previous step
+    prev_segment: f32,
+    /// Ring buffer of recent input, for lookahead
+    delay: Box<[F]>,
+    /// Ring buffer of gains needed to reach the ceiling
+    required: Box<[f32]>,
+    /// Ring buffer of gains after release smoothing
+    envelope: Box<[f32]>,
+    /// Index in the ring buffers of the next value to write
+    cursor: usize,
+    /// Number of steps over which gain is held and averaged
+    window: usize,
+    /// Queue of indices into `required` within the window whose gains are lower than those of
+    /// every later index, so that the first is the window's minimum
+    minima: Box<[usize]>,
+    /// Index in `minima` of the first element
+    minima_head: usize,
+    minima_len: usize,
+    /// Sum of `envelope` over the window
+    sum: f64,
+}
+
+impl<F> State<F> {
+    /// Recompute the minimum and sum for a `window` ending at the most recent step
+    fn reset_window(&mut self, window: usize) {
+        let capacity = self.required.len();
+        self.window = window;
+        self.minima_len = 0;
+        self.sum = 0.0;
+        for age in (1..=window).rev() {
+            let index = (self.cursor + capacity - age) % capacity;
+            self.push_minimum(index);
+            self.sum += f64::from(self.envelope[index]);
+        }
+    }
+
+    /// Add `required[index]` as the most recent value in the window
+    fn push_minimum(&mut self, index: usize) {
+        let capacity = self.minima.len();
+        while self.minima_len > 0 {
+            let last = self.minima[(self.minima_head + self.minima_len - 1) % capacity];
+            if self.required[last] < self.required[index] {
+                break;
+            }
+            self.minima_len -= 1;
+        }
+        self.minima[(self.minima_head + self.minima_len) % capacity] = index;
+        self.minima_len += 1;
+    }
+}
+
+impl<T: Signal + ?Sized> Limiter<T> {
+    /// Seconds by which the signal is delayed
+    pub const LATENCY: f32 = LOOKAHEAD;
+}
+
+impl<T: Signal> Limiter<T>
+where
+    T::Frame: Frame,
+{
+    /// Limit `signal`
+    pub fn new(signal: T, options: LimiterOptions) -> Self {
+        // Windowed sinc interpolation between `history[TAPS / 2 - 1]` and `history[TAPS / 2]`
+        let mut phases = [[0.0; TAPS]; OVERSAMPLING - 1];
+        for (p, phase) in phases.iter_mut().enumerate() {
+            let t = (p + 1) as f32 / OVERSAMPLING as f32;
+            for (j, tap) in phase.iter_mut().enumerate() {
+                let d = j as f32 - (TAPS / 2 - 1) as f32 - t;
+                let sinc = if d == 0.0 {
+                    1.0
+                } else {
+                    (PI * d).sin() / (PI * d)
+                };
+                let window = 0.5 + 0.5 * (PI * d / (TAPS / 2) as f32).cos();
+                *tap = sinc * window;
+            }
+            let sum = phase.iter().sum::<f32>();
+            for tap in phase.iter_mut() {
+                *tap /= sum;
+            }
+        }
+
+        let capacity = (LOOKAHEAD * MAX_SAMPLE_RATE).ceil() as usize + TAPS;
+        Self {
+            options: Swap::new(options),
+            reduction: AtomicU32::new(0.0f32.to_bits()),
+            state: RefCell::new(State {
+                phases,
+                history: [T::Frame::ZERO; TAPS],
+                prev_segment: 0.0,
+                delay: (0..capacity).map(|_| T::Frame::ZERO).collect(),
+                required: (0..capacity).map(|_| 1.0).collect(),
+                envelope: (0..capacity).map(|_| 1.0).collect(),
+                cursor: 0,
+                window: 0,
+                minima: (0..capacity).map(|_| 0).collect(),
+                minima_head: 0,
+                minima_len: 0,
+                sum: 0.0,
+            }),
+            inner: signal,
+        }
+    }
+}
+
+/// Configuration for a [`Limiter`] filter
+#[derive(Debug, Copy, Clone)]
+pub struct LimiterOptions {
+    /// Maximum true peak level, in decibels
+    pub ceiling: f32,
+    /// Time constant, in seconds, with which gain reduction recovers
+    pub release: f32,
+}
+
+impl Default for LimiterOptions {
+    fn default() -> Self {
+        Self {
+            ceiling: -1.0,
+            release: 0.1,
+        }
+    }
+}
+
+impl<T: Signal + ?Sized> Signal for Limiter<T>
+where
+    T::Frame: Frame + Copy,
+{
+    type Frame = T::Frame;
+
+    fn sample(&self, interval: f32, out: &mut [T::Frame]) {
+        self.inner.sample(interval, out);
+        self.options.refresh();
+        let options = unsafe { *self.options.received() };
+        let ceiling = 10.0f32.powf(options.ceiling / 20.0);
+        let release = 1.0 - (-interval / options.release).exp();
+
+        let state = &mut *self.state.borrow_mut();
+        let capacity = state.delay.len();
+        // Gain is held and averaged over `window` steps, and peaks are detected `TAPS / 2` steps
+        // after they're received, so delaying by their sum aligns each peak with the minimum gain
+        let window = ((LOOKAHEAD / interval + 0.5) as usize).clamp(1, capacity - TAPS);
+        let delay = window - 1 + TAPS / 2;
+        let mut gain = 1.0;
+        for x in out {
+            // Recomputing once per trip around the ring buffers keeps rounding error in the running
+            // sum from accumulating
+            if window != state.window || state.cursor == 0 {
+                state.reset_window(window);
+            }
+
+            let input = core::mem::replace(x, T::Frame::ZERO);
+            state.history.rotate_left(1);
+            state.history[TAPS - 1] = input;
+
+            // Estimate the true peak around the frame at the center of the history
+            let center = &state.history[TAPS / 2 - 1];
+            let mut segment = 0.0f32;
+            for channel in 0..center.channels().len() {
+                for phase in &state.phases {
+                    let y = phase
+                        .iter()
+                        .zip(&state.history)
+                        .map(|(&h, x)| h * x.channels()[channel])
+                        .sum::<f32>();
+                    segment = segment.max(y.abs());
+                }
+            }
+            let sample_peak = center
+                .channels()
+                .iter()
+                .fold(0.0f32, |acc, &s| acc.max(s.abs()));
+            let peak = sample_peak.max(segment).max(state.prev_segment);
+            state.prev_segment = segment;
+
+            let cursor = state.cursor;
+            let prev = (cursor + capacity - 1) % capacity;
+            let expired = (cursor + capacity - window) % capacity;
+            state.required[cursor] = if peak > ceiling { ceiling / peak } else { 1.0 };
+            state.push_minimum(cursor);
+            if state.minima[state.minima_head] == expired {
+                state.minima_head = (state.minima_head + 1) % capacity;
+                state.minima_len -= 1;
+            }
+            let held = state.required[state.minima[state.minima_head]];
+            let envelope = state.envelope[prev];
+            let next = if held < envelope {
+                held
+            } else {
+                envelope + release * (held - envelope)
+            };
+            state.sum += f64::from(next) - f64::from(state.envelope[expired]);
+            state.envelope[cursor] = next;
+            gain = (state.sum / window as f64) as f32;
+
+            state.delay[cursor] = input;
+            let delayed = &state.delay[(cursor + capacity - delay) % capacity];
+            *x = frame::scale(delayed, gain);
+            state.cursor = (cursor + 1) % capacity;
+        }
+        self.reduction
+            .store((-20.0 * gain.log10()).to_bits(), Ordering::Relaxed);
+    }
+
+    fn remaining(&self) -> f32 {
+        self.inner.remaining() + LOOKAHEAD
+    }
+
+    #[inline]
+    fn handle_dropped(&self) {
+        self.inner.handle_dropped();
+    }
+}
+
+impl<T: Signal + ?Sized> Filter for Limiter<T> {
+    type Inner = T;
+    fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+/// Thread-safe control for a [`Limiter`] filter
+pub struct LimiterControl<'a> {
+    options: &'a Swap<LimiterOptions>,
+    reduction: &'a AtomicU32,
+}
+
+unsafe impl<'a, T: Signal + 'a> Controlled<'a> for Limiter<T> {
+    type Control = LimiterControl<'a>;
+
+    unsafe fn make_control(signal: &'a Limiter<T>) -> Self::Control {
+        LimiterControl {
+            options: &signal.options,
+            reduction: &signal.reduction,
+        }
+    }
+}
+
+impl<'a> LimiterControl<'a> {
+    /// Replace the filter's configuration
+    pub fn set_options(&mut self, options: LimiterOptions) {
+        unsafe {
+            *self.options.pending() = options;
+        }
+        self.options.flush();
+    }
+
+    /// Get the gain reduction most recently applied, in decibels
+    pub fn gain_reduction(&self) -> f32 {
+        f32::from_bits(self.reduction.load(Ordering::Relaxed))
+    }
+}
+
+/// Seconds over which gain reduction is ramped in ahead of a peak
+const LOOKAHEAD: f32 = 0.0015;
+
+/// Factor by which the signal is oversampled to estimate true peaks
+const OVERSAMPLING: usize = 4;
+
+/// Length of the oversampling interpolation filter
+const TAPS: usize = 8;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Constant, FixedGain, Sine};
+
+    #[test]
+    fn ceiling() {
+        let options = LimiterOptions {
+            ceiling: -1.0,
+            release: 0.01,
+        };
+        let s = Limiter::new(Constant(2.0), options);
+        let mut buf = [0.0; 4800];
+        s.sample(1.0 / 48_000.0, &mut buf);
+        let ceiling = 10.0f32.powf(-1.0 / 20.0);
+        assert!(buf.iter().all(|&x| x <= ceiling + 1e-6));
+        assert!((buf[4799] - ceiling).abs() < 1e-4);
+        let control = unsafe { Limiter::make_control(&s) };
+        assert!((control.gain_reduction() - 20.0 * (2.0 / ceiling).log10()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn true_peak() {
+        // Samples of a quarter-rate sine offset by 45 degrees fall at 71% of its true peak
+        let s = Limiter::new(
+            FixedGain::new(Sine::new(PI / 4.0, 12_000.0), 1.0),
+            LimiterOptions {
+                ceiling: 0.0,
+                release: 0.1,
+            },
+        );
+        let mut buf = [0.0; 480];
+        s.sample(1.0 / 48_000.0, &mut buf);
+        let control = unsafe { Limiter::make_control(&s) };
+        assert!(
+            control.gain_reduction() > 0.8,
+            "{}",
+            control.gain_reduction()
+        );
+    }
+}
@@ -74,6 +74,14 @@ impl Float for f32 {
     fn tanh(self) -> Self {
         libm::tanhf(self)
     }
+
+    fn cos(self) -> Self {
+        libm::cosf(self)
+    }
+
+    fn log2(self) -> Self {
+        libm::log2f(self)
+    }
 }
 
 impl Float for f64 {
@@ -150,4 +158,12 @@ impl Float for f64 {
     fn tanh(self) -> Self {
         libm::tanh(self)
     }
+
+    fn cos(self) -> Self {
+        libm::cos(self)
+    }
+
+    fn log2(self) -> Self {
+        libm::log2(self)
+    }
 }
@@ -4,6 +4,8 @@ mod std;
 #[cfg(feature = "no_std")]
 mod libm;
 
+// Shadowed by inherent methods unless `no_std` is enabled
+#[allow(dead_code)]
 pub(crate) trait Float {
     fn abs(self) -> Self;
 
@@ -28,6 +30,10 @@ pub(crate) trait Float {
     fn rem_euclid(self, rhs: Self) -> Self;
 
     fn tanh(self) -> Self;
+
+    fn cos(self) -> Self;
+
+    fn log2(self) -> Self;
 }
 
 pub fn norm(x: mint::Vector3<f32>) -> f32 {
@@ -48,6 +48,14 @@ impl Float for f32 {
     fn tanh(self) -> Self {
         Self::tanh(self)
     }
+
+    fn cos(self) -> Self {
+        Self::cos(self)
+    }
+
+    fn log2(self) -> Self {
+        Self::log2(self)
+    }
 }
 
 impl Float for f64 {
@@ -98,4 +106,12 @@ impl Float for f64 {
     fn tanh(self) -> Self {
         Self::tanh(self)
     }
+
+    fn cos(self) -> Self {
+        Self::cos(self)
+    }
+
+    fn log2(self) -> Self {
+        Self::log2(self)
+    }
 }
@@ -0,0 +1,141 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::{math::Float, Controlled, Filter, Frame, Seek, Signal};
+
+/// Measures the peak and RMS levels of a signal without modifying it
+///
+/// Metering is opt-in: play a voice with
+/// [`MixerControl::play_metered`](crate::MixerControl::play_metered) to meter it through its
+/// [`Handle`](crate::Handle), or wrap a whole [`Mixer`](crate::Mixer) to meter its output.
+///
+/// Levels are linear, combine all channels, and are only updated while the signal is being
+/// sampled, so a paused voice reports the levels it had when it was paused.
+pub struct Meter<T: ?Sized> {
+    peak: AtomicU32,
+    /// Mean of squared samples
+    power: AtomicU32,
+    inner: T,
+}
+
+impl<T> Meter<T> {
+    /// Meter the output of `signal`
+    pub fn new(signal: T) -> Self {
+        Self {
+            peak: AtomicU32::new(0.0f32.to_bits()),
+            power: AtomicU32::new(0.0f32.to_bits()),
+            inner: signal,
+        }
+    }
+}
+
+impl<T: Signal + ?Sized> Signal for Meter<T>
+where
+    T::Frame: Frame,
+{
+    type Frame = T::Frame;
+
+    fn sample(&self, interval: f32, out: &mut [T::Frame]) {
+        self.inner.sample(interval, out);
+        let peak_decay = (-interval / PEAK_RELEASE).exp();
+        let alpha = 1.0 - (-interval / RMS_PERIOD).exp();
+        let mut peak = f32::from_bits(self.peak.load(Ordering::Relaxed));
+        let mut power = f32::from_bits(self.power.load(Ordering::Relaxed));
+        for x in out.iter() {
+            let channels = x.channels();
+            let frame_peak = channels.iter().fold(0.0f32, |acc, &s| acc.max(s.abs()));
+            let frame_power =
+                channels.iter().map(|&s| s * s).sum::<f32>() / channels.len().max(1) as f32;
+            peak = frame_peak.max(peak * peak_decay);
+            power += alpha * (frame_power - power);
+        }
+        self.peak.store(peak.to_bits(), Ordering::Relaxed);
+        self.power.store(power.to_bits(), Ordering::Relaxed);
+    }
+
+    fn remaining(&self) -> f32 {
+        self.inner.remaining()
+    }
+
+    #[inline]
+    fn handle_dropped(&self) {
+        self.inner.handle_dropped();
+    }
+}
+
+impl<T: ?Sized> Filter for Meter<T> {
+    type Inner = T;
+    fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: Seek + ?Sized> Seek for Meter<T>
+where
+    T::Frame: Frame,
+{
+    fn seek(&self, seconds: f32) {
+        self.inner.seek(seconds);
+    }
+}
+
+/// Thread-safe control for a [`Meter`] filter
+pub struct MeterControl<'a> {
+    peak: &'a AtomicU32,
+    power: &'a AtomicU32,
+}
+
+unsafe impl<'a, T: 'a> Controlled<'a> for Meter<T> {
+    type Control = MeterControl<'a>;
+
+    unsafe fn make_control(signal: &'a Meter<T>) -> Self::Control {
+        MeterControl {
+            peak: &signal.peak,
+            power: &signal.power,
+        }
+    }
+}
+
+impl<'a> MeterControl<'a> {
+    /// Get the recent peak absolute sample value
+    ///
+    /// Rises immediately to meet new peaks, then decays exponentially. Convert to decibels with
+    /// `20.0 * peak.log10()`.
+    pub fn peak(&self) -> f32 {
+        f32::from_bits(self.peak.load(Ordering::Relaxed))
+    }
+
+    /// Get the root mean square sample value, averaged over a few hundred milliseconds
+    ///
+    /// For reference, the RMS value of a sine wave is `amplitude / 2.0f32.sqrt()`.
+    pub fn rms(&self) -> f32 {
+        f32::from_bits(self.power.load(Ordering::Relaxed)).sqrt()
+    }
+}
+
+/// Time constant, in seconds, with which a measured peak decays
+const PEAK_RELEASE: f32 = 0.3;
+
+/// Time constant, in seconds, of the RMS averaging window
+const RMS_PERIOD: f32 = 0.3;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Constant;
+
+    #[test]
+    fn levels() {
+        let mut s = Meter::new(Constant::new([0.5, -0.5]));
+        let mut buf = [[0.0; 2]; 100];
+        s.sample(0.1, &mut buf);
+        let control = unsafe { Meter::make_control(&s) };
+        assert_eq!(control.peak(), 0.5);
+        assert!((control.rms() - 0.5).abs() < 1e-3);
+
+        s.inner.0 = [0.0; 2];
+        s.sample(0.1, &mut buf[..1]);
+        let control = unsafe { Meter::make_control(&s) };
+        assert!(control.peak() < 0.5 && control.peak() > 0.0);
+        assert!(control.rms() < 0.5);
+    }
+}
@@ -1,7 +1,17 @@
-use alloc::{boxed::Box, sync::Arc, vec};
+use alloc::{
+    boxed::Box,
+    collections::btree_map::BTreeMap,
+    sync::{Arc, Weak},
+    vec,
+};
 use core::cell::RefCell;
 
-use crate::{frame, set, Controlled, Frame, Handle, Set, SetHandle, Signal, Stop};
+#[cfg(not(feature = "no_std"))]
+use crate::workers::{AssertSync, Parallel};
+use crate::{
+    frame, set, Action, Command, Controlled, Frame, Handle, Meter, Set, SetHandle, Signal,
+    SignalId, Stop, StopControl, Voices,
+};
 
 /// Handle for controlling a [`Mixer`] from another thread
 pub struct MixerControl<'a, T>(&'a Mixer<T>);
@@ -19,16 +29,136 @@ impl<T> MixerControl<'_, T> {
     where
         S: Signal<Frame = T> + Send + 'static,
     {
-        let signal = Arc::new(Stop::new(signal));
+        self.play_inner(signal, None)
+    }
+
+    /// Like [`play`](Self::play), but the signal can also be controlled together with every other
+    /// signal sharing the same `tag` by passing [`Voices::Tagged`] to e.g. [`pause`](Self::pause)
+    pub fn play_tagged<S>(&mut self, signal: S, tag: u32) -> Handle<Stop<S>>
+    where
+        S: Signal<Frame = T> + Send + 'static,
+    {
+        self.play_inner(signal, Some(tag))
+    }
+
+    /// Like [`play`](Self::play), but also measures the signal's levels, which can be read through
+    /// the [`MeterControl`](crate::MeterControl) obtained from the returned handle
+    ///
+    /// Metering costs a little time for every sample, so it's only done for signals played this
+    /// way.
+    pub fn play_metered<S>(&mut self, signal: S) -> Handle<Stop<Meter<S>>>
+    where
+        S: Signal<Frame = T> + Send + 'static,
+        T: Frame,
+    {
+        self.play_inner(Meter::new(signal), None)
+    }
+
+    fn play_inner<S>(&mut self, signal: S, tag: Option<u32>) -> Handle<Stop<S>>
+    where
+        S: Signal<Frame = T> + Send + 'static,
+    {
+        let signal = Arc::new(Stop::new(signal, tag));
         let handle = unsafe { Handle::from_arc(signal.clone()) };
-        self.0.send.borrow_mut().insert(signal);
+        self.0.send.borrow_mut().insert(signal, 0);
         handle
     }
+
+    /// Begin playing `signal` without a [`Handle`], returning an ID that can be used to access it
+    /// later
+    ///
+    /// Signals with higher `priority` are mixed first. Signals started with [`play`](Self::play)
+    /// have priority 0.
+    ///
+    /// Because there's no [`Handle`], the signal is notified that its handle was dropped as soon
+    /// as it starts, just like a signal whose handle is discarded immediately after
+    /// [`play`](Self::play). For example, a [`Stream`](crate::Stream) inserted this way finishes
+    /// once its buffered data runs out.
+    pub fn insert<S>(&mut self, signal: S, priority: i32) -> SignalId
+    where
+        S: Signal<Frame = T> + Send + 'static,
+    {
+        self.prune();
+        let signal: ErasedSignal<T> = Arc::new(Stop::new(signal, None));
+        let weak = Arc::downgrade(&signal);
+        let id = self.0.send.borrow_mut().insert(signal, priority);
+        self.0.keyed.borrow_mut().0.insert(id, weak);
+        id
+    }
+
+    /// Stop and remove the signal identified by `id`, if it's still playing
+    pub fn remove(&mut self, id: SignalId) {
+        self.0.send.borrow_mut().remove(id);
+    }
+
+    /// Change the priority of the signal identified by `id`, if it's still playing
+    pub fn set_priority(&mut self, id: SignalId, priority: i32) {
+        self.0.send.borrow_mut().set_priority(id, priority);
+    }
+
+    /// Get the control for the signal identified by `id`, if it's still playing
+    pub fn get(&mut self, id: SignalId) -> Option<StopControl<'_>> {
+        self.prune();
+        // SAFETY: The invariant is that a signal's last strong reference is only ever dropped on
+        // this thread, by `SetHandle::gc`: the audio thread passes signals it removes back through
+        // the set's free queue rather than dropping them. `gc` only runs inside calls that take
+        // `&mut self`, and only one `MixerControl` exists at a time, since controls are obtained
+        // through `&mut` borrows of a `Handle`. Every entry left by `prune` was therefore still
+        // alive when `prune` returned, and can't be freed or removed from `keyed` while the
+        // returned control borrows `self`, so both dereferences remain valid for that lifetime.
+        let keyed = unsafe { &*self.0.keyed.as_ptr() };
+        keyed
+            .0
+            .get(&id)
+            .map(|signal| unsafe { (*signal.as_ptr()).control() })
+    }
+
+    /// Forget signals that have left the mixer
+    fn prune(&mut self) {
+        let mut send = self.0.send.borrow_mut();
+        self.0
+            .keyed
+            .borrow_mut()
+            .0
+            .retain(|&id, _| send.contains(id));
+    }
+
+    /// Suspend playback of the selected signals
+    ///
+    /// Like the other commands below, this applies to every selected signal on the same block,
+    /// including signals whose handles have been dropped.
+    pub fn pause(&mut self, voices: Voices) {
+        self.command(voices, Action::Pause);
+    }
+
+    /// Resume playback of the selected signals, if paused
+    pub fn resume(&mut self, voices: Voices) {
+        self.command(voices, Action::Resume);
+    }
+
+    /// Stop the selected signals for good
+    pub fn stop(&mut self, voices: Voices) {
+        self.command(voices, Action::Stop);
+    }
+
+    /// Linearly fade the selected signals to silence over `seconds`, then stop them
+    pub fn fade_out(&mut self, voices: Voices, seconds: f32) {
+        self.command(voices, Action::FadeOut(seconds));
+    }
+
+    fn command(&mut self, voices: Voices, action: Action) {
+        self.0.send.borrow_mut().command(Command { voices, action });
+    }
 }
 
 /// A [`Signal`] that mixes a dynamic set of [`Signal`]s
+///
+/// To measure the levels of individual voices, play them with
+/// [`MixerControl::play_metered`]. To measure the mix as a whole, wrap the mixer in a [`Meter`].
 pub struct Mixer<T> {
     send: RefCell<SetHandle<ErasedSignal<T>>>,
+    /// Signals started with [`MixerControl::insert`]
+    keyed: RefCell<Keyed<T>>,
     recv: RefCell<Inner<T>>,
 }
 
@@ -41,12 +171,38 @@ where
         let (handle, set) = set();
         Self {
             send: RefCell::new(handle),
+            keyed: RefCell::new(Keyed(BTreeMap::new())),
             recv: RefCell::new(Inner {
                 set,
                 buffer: vec![T::ZERO; 1024].into(),
+                #[cfg(not(feature = "no_std"))]
+                parallel: None,
             }),
         }
     }
+
+    /// Construct a mixer that renders its signals in parallel using `workers` additional threads
+    ///
+    /// Signals are divided evenly between the audio thread and the workers, each of which mixes its
+    /// share into a private buffer. The buffers are then summed in a fixed order, so output is
+    /// deterministic. The audio thread busy-waits for workers to finish their share of each block,
+    /// so workers should run at the same real-time priority as the audio thread, and this is only
+    /// worthwhile when the mixer plays many expensive signals. Workers that have gone idle are
+    /// woken with a system call. Both are exceptions to the rule that sampling never waits.
+    ///
+    /// If a signal panics on a worker thread, the panic is reported on the audio thread once every
+    /// worker has finished the block.
+    ///
+    /// Worker threads exit when the mixer is dropped.
+    #[cfg(not(feature = "no_std"))]
+    pub fn with_workers(workers: usize) -> Self
+    where
+        T: Send,
+    {
+        let mixer = Self::new();
+        mixer.recv.borrow_mut().parallel = Some(Parallel::new(workers, 1024));
+        mixer
+    }
 }
 
 impl<T> Default for Mixer<T>
@@ -69,6 +225,8 @@ unsafe impl<'a, T: 'a> Controlled<'a> for Mixer<T> {
 struct Inner<T> {
     set: Set<ErasedSignal<T>>,
     buffer: Box<[T]>,
+    #[cfg(not(feature = "no_std"))]
+    parallel: Option<Parallel<T>>,
 }
 
 impl<T: Frame> Signal for Mixer<T> {
@@ -78,10 +236,7 @@ impl<T: Frame> Signal for Mixer<T> {
         let this = &mut *self.recv.borrow_mut();
         this.set.update();
 
-        for o in out.iter_mut() {
-            *o = T::ZERO;
-        }
-
+        // Retire finished signals
         for i in (0..this.set.len()).rev() {
             let signal = &this.set[i];
             if Arc::strong_count(signal) == 1 {
@@ -92,24 +247,158 @@ impl<T: Frame> Signal for Mixer<T> {
             }
             if signal.is_stopped() {
                 this.set.remove(i);
-                continue;
             }
-            if signal.is_paused() {
-                continue;
+        }
+
+        #[cfg(not(feature = "no_std"))]
+        if let Some(ref mut parallel) = this.parallel {
+            let set = AssertSync(&this.set);
+            for chunk in out.chunks_mut(parallel.capacity()) {
+                parallel.mix(chunk, |participant, participants, buffer| {
+                    let mut staging = [T::ZERO; 256];
+                    for signal in set.0.iter().skip(participant).step_by(participants) {
+                        if !signal.is_paused() {
+                            mix_signal(&**signal, interval, &mut staging, buffer);
+                        }
+                    }
+                });
             }
+            return;
+        }
 
-            // Sample into `buffer`, then mix into `out`
-            let mut iter = out.iter_mut();
-            while iter.len() > 0 {
-                let n = iter.len().min(this.buffer.len());
-                let staging = &mut this.buffer[..n];
-                signal.sample(interval, staging);
-                for (staged, o) in staging.iter().zip(&mut iter) {
-                    *o = frame::mix(o, staged);
-                }
+        for o in out.iter_mut() {
+            *o = T::ZERO;
+        }
+        for signal in this.set.iter() {
+            if !signal.is_paused() {
+                mix_signal(&**signal, interval, &mut this.buffer, out);
             }
         }
     }
 }
 
+/// Sample `signal` into `staging` in chunks, mixing each into `out`
+fn mix_signal<T: Frame, S: Signal<Frame = T> + ?Sized>(
+    signal: &Stop<S>,
+    interval: f32,
+    staging: &mut [T],
+    out: &mut [T],
+) {
+    let mut iter = out.iter_mut();
+    while iter.len() > 0 {
+        let n = iter.len().min(staging.len());
+        let staging = &mut staging[..n];
+        signal.sample(interval, staging);
+        signal.fade(interval, staging);
+        for (staged, o) in staging.iter().zip(&mut iter) {
+            *o = frame::mix(o, staged);
+        }
+    }
+}
+
 type ErasedSignal<T> = Arc<Stop<dyn Signal<Frame = T>>>;
+
+/// Weak, so that the mixer can tell when no [`Handle`] remains
+struct Keyed<T>(BTreeMap<SignalId, Weak<Stop<dyn Signal<Frame = T>>>>);
+
+// Sound because `MixerControl::insert` requires signals to be `Send`
+unsafe impl<T> Send for Keyed<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Constant;
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    #[test]
+    #[cfg(not(feature = "no_std"))]
+    fn parallel() {
+        let serial = Mixer::new();
+        let parallel = Mixer::with_workers(2);
+        for i in 0..7 {
+            let x = i as f32;
+            unsafe {
+                Mixer::make_control(&serial).play(Constant::new(x));
+                Mixer::make_control(&parallel).play(Constant::new(x));
+            }
+        }
+        let mut expected = [0.0; 1500];
+        let mut actual = [0.0; 1500];
+        serial.sample(0.1, &mut expected);
+        parallel.sample(0.1, &mut actual);
+        assert_eq!(expected, actual);
+        assert_eq!(actual[0], 21.0);
+    }
+
+    #[test]
+    fn metered() {
+        let mixer = Mixer::new();
+        let mut control = unsafe { Mixer::make_control(&mixer) };
+        let mut quiet = control.play_metered(Constant::new(0.25));
+        let mut loud = control.play_metered(Constant::new(-0.5));
+        let mut buf = [0.0; 4];
+        mixer.sample(0.1, &mut buf);
+        assert_eq!(buf, [-0.25; 4]);
+        assert_eq!(quiet.control::<Meter<_>, _>().peak(), 0.25);
+        assert_eq!(loud.control::<Meter<_>, _>().peak(), 0.5);
+    }
+
+    #[test]
+    fn fade_out() {
+        let mixer = Mixer::new();
+        let mut control = unsafe { Mixer::make_control(&mixer) };
+        control.play(Constant::new(1.0));
+        control.fade_out(Voices::All, 0.4);
+        let mut buf = [0.0; 4];
+        mixer.sample(0.1, &mut buf);
+        for (x, y) in buf.iter().zip(&[1.0, 0.75, 0.5, 0.25]) {
+            assert!((x - y).abs() < 1e-6, "{} vs {}", x, y);
+        }
+        mixer.sample(0.1, &mut buf);
+        assert_eq!(buf, [0.0; 4]);
+    }
+
+    #[test]
+    fn keyed() {
+        let mixer = Mixer::new();
+        let mut control = unsafe { Mixer::make_control(&mixer) };
+        let a = control.insert(Constant::new(1.0), 0);
+        let b = control.insert(Constant::new(2.0), 1);
+        let mut buf = [0.0; 2];
+        mixer.sample(0.1, &mut buf);
+        assert_eq!(buf, [3.0; 2]);
+
+        control.get(b).unwrap().pause();
+        mixer.sample(0.1, &mut buf);
+        assert_eq!(buf, [1.0; 2]);
+
+        control.remove(a);
+        mixer.sample(0.1, &mut buf);
+        assert_eq!(buf, [0.0; 2]);
+        assert!(control.get(a).is_none());
+        assert!(control.get(b).unwrap().is_paused());
+    }
+
+    #[test]
+    fn keyed_handle_dropped() {
+        struct Notified(Arc<AtomicBool>);
+
+        impl Signal for Notified {
+            type Frame = f32;
+            fn sample(&self, _: f32, out: &mut [f32]) {
+                out.fill(0.0);
+            }
+            fn handle_dropped(&self) {
+                self.0.store(true, Ordering::Relaxed);
+            }
+        }
+
+        let mixer = Mixer::new();
+        let mut control = unsafe { Mixer::make_control(&mixer) };
+        let notified = Arc::new(AtomicBool::new(false));
+        let id = control.insert(Notified(notified.clone()), 0);
+        mixer.sample(0.1, &mut [0.0; 2]);
+        assert!(notified.load(Ordering::Relaxed));
+        assert!(control.get(id).is_some());
+    }
+}
@@ -0,0 +1,535 @@
+//! Effects driven by a low-frequency oscillator (LFO)
+//!
+//! Each channel's LFO is a quarter cycle ahead of the last, so stereo signals gain a sense of
+//! width and motion.
+
+use core::{
+    cell::RefCell,
+    f32::consts::{PI, TAU},
+};
+
+use crate::{
+    biquad::CHUNK_SIZE, compressor::MAX_SAMPLE_RATE, delay::echo_tail, frame, math::Float,
+    ring::Ring, swap::Swap, Controlled, Filter, Frame, Signal,
+};
+
+/// Thickens a signal by mixing in copies whose delay is slowly modulated, like several performers
+/// playing in unison
+pub struct Chorus<T: Signal + ?Sized> {
+    options: Swap<ChorusOptions>,
+    state: RefCell<ModulatedDelay<T::Frame>>,
+    inner: T,
+}
+
+impl<T: Signal + ?Sized> Chorus<T> {
+    /// Maximum delay, in seconds
+    pub const MAX_DELAY: f32 = MAX_DELAY;
+
+    /// Maximum number of voices
+    pub const MAX_VOICES: u32 = 4;
+}
+
+impl<T: Signal> Chorus<T>
+where
+    T::Frame: Frame + Copy,
+{
+    /// Apply chorus to `signal`
+    pub fn new(signal: T, options: ChorusOptions) -> Self {
+        Self {
+            options: Swap::new(options),
+            state: RefCell::new(ModulatedDelay::new()),
+            inner: signal,
+        }
+    }
+}
+
+/// Configuration for a [`Chorus`] filter
+#[derive(Debug, Copy, Clone)]
+pub struct ChorusOptions {
+    /// LFO cycles per second
+    pub rate: f32,
+    /// Seconds by which each voice's delay swings either side of `delay`
+    pub depth: f32,
+    /// Average delay of each voice, in seconds. Together with `depth`, limited to
+    /// [`Chorus::MAX_DELAY`].
+    pub delay: f32,
+    /// Number of voices, spread evenly through the LFO's cycle. Limited to
+    /// [`Chorus::MAX_VOICES`].
+    pub voices: u32,
+    /// Proportion of the voices fed back into the delay line
+    pub feedback: f32,
+    /// Proportion of the output made up of the voices, from 0 (dry) to 1 (wet)
+    pub mix: f32,
+}
+
+impl Default for ChorusOptions {
+    fn default() -> Self {
+        Self {
+            rate: 0.5,
+            depth: 0.003,
+            delay: 0.02,
+            voices: 3,
+            feedback: 0.0,
+            mix: 0.5,
+        }
+    }
+}
+
+impl<T: Signal + ?Sized> Signal for Chorus<T>
+where
+    T::Frame: Frame + Copy,
+{
+    type Frame = T::Frame;
+
+    fn sample(&self, interval: f32, out: &mut [T::Frame]) {
+        self.inner.sample(interval, out);
+        self.options.refresh();
+        let o = unsafe { *self.options.received() };
+        let voices = o.voices.clamp(1, Self::MAX_VOICES);
+        let mut state = self.state.borrow_mut();
+        for x in out {
+            *x = state.process(
+                x, interval, o.rate, o.delay, o.depth, voices, o.feedback, o.mix,
+            );
+        }
+    }
+
+    fn remaining(&self) -> f32 {
+        self.options.refresh();
+        let o = unsafe { &*self.options.received() };
+        self.inner.remaining() + echo_tail(longest_delay(o.delay, o.depth), o.feedback)
+    }
+
+    #[inline]
+    fn handle_dropped(&self) {
+        self.inner.handle_dropped();
+    }
+}
+
+impl<T: Signal + ?Sized> Filter for Chorus<T> {
+    type Inner = T;
+    fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+/// Thread-safe control for a [`Chorus`] filter
+pub struct ChorusControl<'a>(&'a Swap<ChorusOptions>);
+
+unsafe impl<'a, T: Signal + 'a> Controlled<'a> for Chorus<T> {
+    type Control = ChorusControl<'a>;
+
+    unsafe fn make_control(signal: &'a Chorus<T>) -> Self::Control {
+        ChorusControl(&signal.options)
+    }
+}
+
+impl<'a> ChorusControl<'a> {
+    /// Replace the filter's configuration
+    pub fn set_options(&mut self, options: ChorusOptions) {
+        unsafe {
+            *self.0.pending() = options;
+        }
+        self.0.flush();
+    }
+}
+
+/// Sweeps a series of notches through a signal's spectrum by mixing it with a copy whose delay is
+/// very short and slowly modulated, producing a jet-like whoosh
+///
+/// Negative feedback emphasizes odd harmonics of the delay, giving a hollower sound.
+pub struct Flanger<T: Signal + ?Sized> {
+    options: Swap<FlangerOptions>,
+    state: RefCell<ModulatedDelay<T::Frame>>,
+    inner: T,
+}
+
+impl<T: Signal + ?Sized> Flanger<T> {
+    /// Maximum delay, in seconds
+    pub const MAX_DELAY: f32 = MAX_DELAY;
+}
+
+impl<T: Signal> Flanger<T>
+where
+    T::Frame: Frame + Copy,
+{
+    /// Apply flanging to `signal`
+    pub fn new(signal: T, options: FlangerOptions) -> Self {
+        Self {
+            options: Swap::new(options),
+            state: RefCell::new(ModulatedDelay::new()),
+            inner: signal,
+        }
+    }
+}
+
+/// Configuration for a [`Flanger`] filter
+#[derive(Debug, Copy, Clone)]
+pub struct FlangerOptions {
+    /// LFO cycles per second
+    pub rate: f32,
+    /// Seconds by which the delay swings either side of `delay`
+    pub depth: f32,
+    /// Average delay, in seconds. Together with `depth`, limited to [`Flanger::MAX_DELAY`].
+    pub delay: f32,
+    /// Proportion of the delayed signal fed back into the delay line, from -1 to 1 exclusive
+    pub feedback: f32,
+    /// Proportion of the output made up of the delayed signal, from 0 (dry) to 1 (wet). 0.5 gives
+    /// the deepest notches.
+    pub mix: f32,
+}
+
+impl Default for FlangerOptions {
+    fn default() -> Self {
+        Self {
+            rate: 0.2,
+            depth: 0.002,
+            delay: 0.0025,
+            feedback: 0.5,
+            mix: 0.5,
+        }
+    }
+}
+
+impl<T: Signal + ?Sized> Signal for Flanger<T>
+where
+    T::Frame: Frame + Copy,
+{
+    type Frame = T::Frame;
+
+    fn sample(&self, interval: f32, out: &mut [T::Frame]) {
+        self.inner.sample(interval, out);
+        self.options.refresh();
+        let o = unsafe { *self.options.received() };
+        let mut state = self.state.borrow_mut();
+        for x in out {
+            *x = state.process(x, interval, o.rate, o.delay, o.depth, 1, o.feedback, o.mix);
+        }
+    }
+
+    fn remaining(&self) -> f32 {
+        self.options.refresh();
+        let o = unsafe { &*self.options.received() };
+        self.inner.remaining() + echo_tail(longest_delay(o.delay, o.depth), o.feedback)
+    }
+
+    #[inline]
+    fn handle_dropped(&self) {
+        self.inner.handle_dropped();
+    }
+}
+
+impl<T: Signal + ?Sized> Filter for Flanger<T> {
+    type Inner = T;
+    fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+/// Thread-safe control for a [`Flanger`] filter
+pub struct FlangerControl<'a>(&'a Swap<FlangerOptions>);
+
+unsafe impl<'a, T: Signal + 'a> Controlled<'a> for Flanger<T> {
+    type Control = FlangerControl<'a>;
+
+    unsafe fn make_control(signal: &'a Flanger<T>) -> Self::Control {
+        FlangerControl(&signal.options)
+    }
+}
+
+impl<'a> FlangerControl<'a> {
+    /// Replace the filter's configuration
+    pub fn set_options(&mut self, options: FlangerOptions) {
+        unsafe {
+            *self.0.pending() = options;
+        }
+        self.0.flush();
+    }
+}
+
+/// Sweeps a series of notches through a signal's spectrum by mixing it with a copy passed through
+/// a chain of allpass filters whose frequency is slowly modulated
+///
+/// Unlike a [`Flanger`]'s, the notches aren't harmonically spaced, giving a smoother, more
+/// synthetic sound. Each pair of stages adds a notch.
+pub struct Phaser<T: Signal + ?Sized> {
+    options: Swap<PhaserOptions>,
+    state: RefCell<PhaserState<T::Frame>>,
+    inner: T,
+}
+
+struct PhaserState<F> {
+    lfo: f32,
+    /// Allpass filter state for each stage
+    stages: [F; MAX_STAGES],
+    /// Output of the final stage, for feedback
+    last: F,
+}
+
+impl<T: Signal + ?Sized> Phaser<T> {
+    /// Maximum number of allpass stages
+    pub const MAX_STAGES: u32 = MAX_STAGES as u32;
+}
+
+impl<T: Signal> Phaser<T>
+where
+    T::Frame: Frame + Copy,
+{
+    /// Apply phasing to `signal`
+    pub fn new(signal: T, options: PhaserOptions) -> Self {
+        Self {
+            options: Swap::new(options),
+            state: RefCell::new(PhaserState {
+                lfo: 0.0,
+                stages: [T::Frame::ZERO; MAX_STAGES],
+                last: T::Frame::ZERO,
+            }),
+            inner: signal,
+        }
+    }
+}
+
+/// Configuration for a [`Phaser`] filter
+#[derive(Debug, Copy, Clone)]
+pub struct PhaserOptions {
+    /// LFO cycles per second
+    pub rate: f32,
+    /// Octaves by which the allpass frequency swings either side of `frequency`
+    pub depth: f32,
+    /// Average allpass frequency, in Hz
+    pub frequency: f32,
+    /// Number of allpass stages. Limited to [`Phaser::MAX_STAGES`].
+    pub stages: u32,
+    /// Proportion of the final stage's output fed back into the first, from -1 to 1 exclusive
+    pub feedback: f32,
+    /// Proportion of the output made up of the filtered signal, from 0 (dry) to 1 (wet). 0.5 gives
+    /// the deepest notches.
+    pub mix: f32,
+}
+
+impl Default for PhaserOptions {
+    fn default() -> Self {
+        Self {
+            rate: 0.5,
+            depth: 2.0,
+            frequency: 800.0,
+            stages: 4,
+            feedback: 0.5,
+            mix: 0.5,
+        }
+    }
+}
+
+impl<T: Signal + ?Sized> Signal for Phaser<T>
+where
+    T::Frame: Frame + Copy,
+{
+    type Frame = T::Frame;
+
+    fn sample(&self, interval: f32, out: &mut [T::Frame]) {
+        self.inner.sample(interval, out);
+        self.options.refresh();
+        let o = unsafe { *self.options.received() };
+        let stages = o.stages.clamp(1, Self::MAX_STAGES) as usize;
+        let state = &mut *self.state.borrow_mut();
+        let mut coefficients = T::Frame::ZERO;
+        for chunk in out.chunks_mut(CHUNK_SIZE) {
+            // The sweep is slow, so coefficients are only updated once per chunk
+            for (c, a) in coefficients.channels_mut().iter_mut().enumerate() {
+                let lfo = (TAU * (state.lfo + c as f32 * CHANNEL_OFFSET)).sin();
+                let frequency = (o.frequency * 2.0f32.powf(o.depth * lfo)).min(0.49 / interval);
+                let t = PI * frequency * interval;
+                let tan = t.sin() / t.cos();
+                *a = (tan - 1.0) / (tan + 1.0);
+            }
+            for x in chunk.iter_mut() {
+                let mut y = frame::mix(x, &frame::scale(&state.last, o.feedback));
+                for s in &mut state.stages[..stages] {
+                    // First-order allpass, transposed direct form II
+                    for ((y, s), &a) in y
+                        .channels_mut()
+                        .iter_mut()
+                        .zip(s.channels_mut())
+                        .zip(coefficients.channels())
+                    {
+                        let input = *y;
+                        *y = a * input + *s;
+                        *s = input - a * *y;
+                    }
+                }
+                state.last = y;
+                *x = frame::lerp(x, &y, o.mix);
+            }
+            state.lfo = (state.lfo + o.rate * interval * chunk.len() as f32).fract();
+        }
+    }
+
+    fn remaining(&self) -> f32 {
+        self.options.refresh();
+        let o = unsafe { &*self.options.received() };
+        // A first-order allpass delays frequencies near its own by about a radian
+        let lowest = o.frequency * 2.0f32.powf(-o.depth.abs());
+        let time = o.stages.clamp(1, Self::MAX_STAGES) as f32 / (TAU * lowest);
+        self.inner.remaining() + echo_tail(time, o.feedback)
+    }
+
+    #[inline]
+    fn handle_dropped(&self) {
+        self.inner.handle_dropped();
+    }
+}
+
+impl<T: Signal + ?Sized> Filter for Phaser<T> {
+    type Inner = T;
+    fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+/// Thread-safe control for a [`Phaser`] filter
+pub struct PhaserControl<'a>(&'a Swap<PhaserOptions>);
+
+unsafe impl<'a, T: Signal + 'a> Controlled<'a> for Phaser<T> {
+    type Control = PhaserControl<'a>;
+
+    unsafe fn make_control(signal: &'a Phaser<T>) -> Self::Control {
+        PhaserControl(&signal.options)
+    }
+}
+
+impl<'a> PhaserControl<'a> {
+    /// Replace the filter's configuration
+    pub fn set_options(&mut self, options: PhaserOptions) {
+        unsafe {
+            *self.0.pending() = options;
+        }
+        self.0.flush();
+    }
+}
+
+/// Delay line read at LFO-modulated positions, shared by [`Chorus`] and [`Flanger`]
+struct ModulatedDelay<F> {
+    ring: Ring<F>,
+    lfo: f32,
+}
+
+impl<F: Frame + Copy> ModulatedDelay<F> {
+    fn new() -> Self {
+        Self {
+            ring: Ring::new((MAX_DELAY * MAX_SAMPLE_RATE).ceil() as usize + 2),
+            lfo: 0.0,
+        }
+    }
+
+    /// Process a single frame, returning the output
+    #[allow(clippy::too_many_arguments)]
+    fn process(
+        &mut self,
+        x: &F,
+        interval: f32,
+        rate: f32,
+        delay: f32,
+        depth: f32,
+        voices: u32,
+        feedback: f32,
+        mix: f32,
+    ) -> F {
+        let max_delay = (self.ring.capacity() - 2) as f32;
+        let mut wet = F::ZERO;
+        for voice in 0..voices {
+            let phase = self.lfo + voice as f32 / voices as f32;
+            for (c, wet) in wet.channels_mut().iter_mut().enumerate() {
+                let lfo = (TAU * (phase + c as f32 * CHANNEL_OFFSET)).sin();
+                let time = (delay + depth * lfo).clamp(0.0, MAX_DELAY);
+                let delayed = self
+                    .ring
+                    .sample(1, -(time / interval).clamp(1.0, max_delay));
+                *wet += delayed.channels()[c] / voices as f32;
+            }
+        }
+        self.ring.push(frame::mix(x, &frame::scale(&wet, feedback)));
+        self.lfo = (self.lfo + rate * interval).fract();
+        frame::lerp(x, &wet, mix)
+    }
+}
+
+/// Maximum delay of a [`Chorus`] or [`Flanger`], in seconds
+const MAX_DELAY: f32 = 0.05;
+
+/// Longest delay, in seconds, reached by an LFO swinging `depth` either side of `delay`
+fn longest_delay(delay: f32, depth: f32) -> f32 {
+    (delay + depth.abs()).clamp(0.0, MAX_DELAY)
+}
+
+/// Cycles by which each channel's LFO leads the previous channel's
+const CHANNEL_OFFSET: f32 = 0.25;
+
+const MAX_STAGES: usize = 12;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Constant, Frames, FramesSignal, Sine};
+
+    #[test]
+    fn chorus_unity() {
+        let s = Chorus::new(Constant(1.0), ChorusOptions::default());
+        let mut buf = [0.0; 4800];
+        s.sample(1.0 / 48_000.0, &mut buf);
+        // Once the delay line has filled, the voices of a constant signal sum to the same constant
+        assert!(buf[2400..].iter().all(|&x| (x - 1.0).abs() < 1e-5));
+    }
+
+    #[test]
+    fn flanger_feedback() {
+        let options = FlangerOptions {
+            rate: 0.0,
+            depth: 0.0,
+            delay: 2.0,
+            feedback: 0.5,
+            mix: 0.5,
+        };
+        let s = Flanger::new(
+            FramesSignal::new(Frames::from_slice(1000, &[1.0]), 0.0),
+            options,
+        );
+        let mut buf = [0.0; 60];
+        s.sample(1.0 / 1000.0, &mut buf);
+        // Delay is limited to `MAX_DELAY`, 50 samples
+        let echoes = buf.iter().enumerate().filter(|&(_, &x)| x != 0.0);
+        assert!(echoes.map(|(i, _)| i).eq([0, 50]));
+        assert_eq!(buf[50], 0.5);
+        // The echo, and those fed back from it until they fall by 60 dB
+        let tail = 0.05 * (1.0 - 3.0 / 0.5f32.log10());
+        assert!((s.remaining() - s.inner().remaining() - tail).abs() < 1e-5);
+
+        let mut buf = [0.0; 6];
+
+        let s = Flanger::new(
+            FramesSignal::new(Frames::from_slice(1000, &[1.0]), 0.0),
+            FlangerOptions {
+                delay: 0.002,
+                ..options
+            },
+        );
+        s.sample(1.0 / 1000.0, &mut buf);
+        assert_eq!(buf, [0.5, 0.0, 0.5, 0.0, 0.25, 0.0]);
+    }
+
+    #[test]
+    fn phaser_notch() {
+        // Two stages shift a sinusoid at their frequency by half a cycle, cancelling it
+        let options = PhaserOptions {
+            rate: 0.0,
+            depth: 0.0,
+            frequency: 1000.0,
+            stages: 2,
+            feedback: 0.0,
+            mix: 0.5,
+        };
+        let s = Phaser::new(Sine::new(0.0, 1000.0), options);
+        let mut buf = [0.0; 4800];
+        s.sample(1.0 / 48_000.0, &mut buf);
+        assert!(buf[2400..].iter().all(|x| x.abs() < 1e-3));
+    }
+}
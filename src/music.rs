@@ -0,0 +1,530 @@
+use alloc::{sync::Arc, vec::Vec};
+use core::cell::{RefCell, UnsafeCell};
+
+use crate::{frame, math::Float, spsc, swap::Swap, Controlled, Curve, Frame, Frames, Signal};
+
+/// A [`Signal`] that plays music, switching between tracks in time with the beat
+///
+/// Each [`MusicTrack`] describes the tempo and bar layout of its audio, which allows a transition
+/// to the next track to be deferred until the next beat, bar, or marker of the track that's
+/// playing. Transitions can crossfade between tracks, and can play a [stinger](Transition::stinger)
+/// to bridge them.
+///
+/// Only the most recent request made through a [`MusicPlayerControl`] takes effect: a transition
+/// that's still waiting for its sync point is replaced by any that follows it. Tracks and stingers
+/// that are no longer needed are handed back to be freed by the next call to the control.
+pub struct MusicPlayer<T> {
+    requests: Swap<Option<Request<T>>>,
+    free: UnsafeCell<spsc::Receiver<Free<T>>>,
+    state: RefCell<State<T>>,
+}
+
+impl<T> MusicPlayer<T> {
+    /// Construct a player that's initially silent
+    pub fn new() -> Self {
+        let (free_send, free_recv) = spsc::channel(FREE_CAPACITY);
+        Self {
+            requests: Swap::new(None),
+            free: UnsafeCell::new(free_recv),
+            state: RefCell::new(State {
+                current: None,
+                outgoing: None,
+                stinger: None,
+                pending: None,
+                free: free_send,
+            }),
+        }
+    }
+}
+
+/// Number of retired values that can await freeing
+///
+/// Between two calls to the control, at most one new request can arrive, so the values that may be
+/// retired are limited to those held by the player and that request.
+const FREE_CAPACITY: usize = 16;
+
+/// A value retired by the audio thread, to be freed by the control
+// Fields are never read, only dropped
+#[allow(dead_code)]
+enum Free<T> {
+    Track(MusicTrack<T>),
+    Stinger(Arc<Frames<T>>),
+}
+
+impl<T> Default for MusicPlayer<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A piece of music that can be played by a [`MusicPlayer`]
+pub struct MusicTrack<T> {
+    /// Audio data
+    pub frames: Arc<Frames<T>>,
+    /// Tempo, in beats per minute
+    pub bpm: f32,
+    /// Number of beats in each bar
+    pub beats_per_bar: u32,
+    /// Seconds into `frames` of the first downbeat
+    pub offset: f64,
+    /// Seconds into `frames` at which [`TransitionPoint::Marker`] transitions may occur, in
+    /// increasing order
+    pub markers: Vec<f64>,
+    /// Whether to restart from the beginning on reaching the end, rather than stopping
+    pub looping: bool,
+}
+
+impl<T> MusicTrack<T> {
+    /// Describe a looping track at `bpm` with `beats_per_bar` beats in each bar, beginning on a
+    /// downbeat, and without markers
+    pub fn new(frames: Arc<Frames<T>>, bpm: f32, beats_per_bar: u32) -> Self {
+        Self {
+            frames,
+            bpm,
+            beats_per_bar,
+            offset: 0.0,
+            markers: Vec::new(),
+            looping: true,
+        }
+    }
+
+    /// Seconds from position `t` until the next occurrence of `point`
+    ///
+    /// If no such point remains before the end, a looping track uses the first in the next
+    /// iteration, and other tracks use the end.
+    fn until(&self, t: f64, point: TransitionPoint) -> f64 {
+        let runtime = self.frames.runtime();
+        let first = match point {
+            TransitionPoint::Immediate => return 0.0,
+            TransitionPoint::Beat | TransitionPoint::Bar => {
+                let mut period = 60.0 / f64::from(self.bpm);
+                if point == TransitionPoint::Bar {
+                    period *= f64::from(self.beats_per_bar);
+                }
+                let next = self.offset + ((t - self.offset) / period).ceil().max(0.0) * period;
+                if next < runtime {
+                    return next - t;
+                }
+                self.offset
+            }
+            TransitionPoint::Marker => {
+                if let Some(&next) = self.markers.iter().find(|&&m| m >= t) {
+                    return next - t;
+                }
+                match self.markers.first() {
+                    Some(&x) => x,
+                    None => return (runtime - t).max(0.0),
+                }
+            }
+        };
+        if self.looping {
+            runtime - t + first
+        } else {
+            (runtime - t).max(0.0)
+        }
+    }
+}
+
+impl<T> Clone for MusicTrack<T> {
+    fn clone(&self) -> Self {
+        Self {
+            frames: self.frames.clone(),
+            bpm: self.bpm,
+            beats_per_bar: self.beats_per_bar,
+            offset: self.offset,
+            markers: self.markers.clone(),
+            looping: self.looping,
+        }
+    }
+}
+
+/// When a [`Transition`] takes place, relative to the track that's playing
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum TransitionPoint {
+    /// As soon as possible
+    Immediate,
+    /// On the next beat
+    #[default]
+    Beat,
+    /// On the next downbeat
+    Bar,
+    /// On the next of the track's [markers](MusicTrack::markers)
+    Marker,
+}
+
+/// How a [`MusicPlayer`] moves from one track to the next
+pub struct Transition<T> {
+    /// When the transition takes place
+    pub point: TransitionPoint,
+    /// Seconds over which the outgoing track fades out and the incoming track fades in
+    pub crossfade: f32,
+    /// Shape of the fade in. The fade out is its complement, so the two always sum to 1.
+    pub curve: Curve,
+    /// Audio played once at the transition point, after which the incoming track begins
+    pub stinger: Option<Arc<Frames<T>>>,
+}
+
+impl<T> Default for Transition<T> {
+    fn default() -> Self {
+        Self {
+            point: TransitionPoint::default(),
+            crossfade: 0.0,
+            curve: Curve::Linear,
+            stinger: None,
+        }
+    }
+}
+
+impl<T> Clone for Transition<T> {
+    fn clone(&self) -> Self {
+        Self {
+            point: self.point,
+            crossfade: self.crossfade,
+            curve: self.curve,
+            stinger: self.stinger.clone(),
+        }
+    }
+}
+
+struct Request<T> {
+    /// Track to play, or `None` to fall silent
+    track: Option<MusicTrack<T>>,
+    transition: Transition<T>,
+}
+
+impl<T> Clone for Request<T> {
+    fn clone(&self) -> Self {
+        Self {
+            track: self.track.clone(),
+            transition: self.transition.clone(),
+        }
+    }
+}
+
+struct State<T> {
+    current: Option<Deck<T>>,
+    /// Track fading out after a transition
+    outgoing: Option<Deck<T>>,
+    /// Stinger being played, and seconds into it
+    stinger: Option<(Arc<Frames<T>>, f64)>,
+    /// Transition waiting for its sync point, and seconds until then
+    pending: Option<(Request<T>, f64)>,
+    free: spsc::Sender<Free<T>>,
+}
+
+/// A track in progress
+struct Deck<T> {
+    track: MusicTrack<T>,
+    /// Seconds into the track, or before it begins if negative
+    t: f64,
+    fade: Fade,
+}
+
+impl<T: Frame + Copy> Deck<T> {
+    fn get(&self) -> T {
+        if self.t < 0.0 {
+            return T::ZERO;
+        }
+        let x = self
+            .track
+            .frames
+            .interpolate(self.t * f64::from(self.track.frames.rate()));
+        frame::scale(&x, self.fade.gain())
+    }
+
+    /// Advance by `seconds`, returning `false` if the deck has finished
+    fn advance(&mut self, seconds: f64) -> bool {
+        let started = self.t >= 0.0;
+        self.t += seconds;
+        if started {
+            self.fade.elapsed += seconds as f32;
+        }
+        let runtime = self.track.frames.runtime();
+        if self.t < runtime {
+            return true;
+        }
+        if self.track.looping && runtime > 0.0 {
+            self.t %= runtime;
+            return true;
+        }
+        false
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Fade {
+    elapsed: f32,
+    duration: f32,
+    curve: Curve,
+    out: bool,
+}
+
+impl Fade {
+    fn gain(&self) -> f32 {
+        let x = if self.duration > 0.0 {
+            self.elapsed / self.duration
+        } else {
+            1.0
+        };
+        let x = self.curve.apply(x);
+        if self.out {
+            1.0 - x
+        } else {
+            x
+        }
+    }
+
+    fn is_silent(&self) -> bool {
+        self.out && self.elapsed >= self.duration
+    }
+}
+
+impl<T> State<T> {
+    /// Hand `value` back to the control to be freed
+    fn retire(&mut self, value: Free<T>) {
+        // The queue has room for everything the player can retire between calls to the control,
+        // so this only fails if the control is never used again; freeing here is then harmless.
+        let _ = self.free.send(value, 0);
+    }
+
+    fn retire_deck(&mut self, deck: Option<Deck<T>>) {
+        if let Some(deck) = deck {
+            self.retire(Free::Track(deck.track));
+        }
+    }
+
+    fn retire_stinger(&mut self, stinger: Option<Arc<Frames<T>>>) {
+        if let Some(stinger) = stinger {
+            self.retire(Free::Stinger(stinger));
+        }
+    }
+
+    /// Replace any pending transition with `request`
+    fn schedule(&mut self, request: Request<T>) {
+        let wait = self.current.as_ref().map_or(0.0, |deck| {
+            deck.track.until(deck.t.max(0.0), request.transition.point) + (-deck.t).max(0.0)
+        });
+        if let Some((old, _)) = self.pending.replace((request, wait)) {
+            if let Some(track) = old.track {
+                self.retire(Free::Track(track));
+            }
+            self.retire_stinger(old.transition.stinger);
+        }
+    }
+
+    /// Begin the pending transition
+    fn execute(&mut self, request: Request<T>) {
+        let Request { track, transition } = request;
+        let fade = Fade {
+            elapsed: 0.0,
+            duration: transition.crossfade,
+            curve: transition.curve,
+            out: false,
+        };
+        let delay = transition
+            .stinger
+            .as_ref()
+            .map_or(0.0, |stinger| stinger.runtime());
+        let outgoing = self.current.take().map(|mut deck| {
+            // Begin fading out from the current level, in case a fade in was in progress
+            let level = deck.fade.gain();
+            deck.fade = Fade {
+                out: true,
+                elapsed: transition.crossfade * (1.0 - level),
+                ..fade
+            };
+            deck
+        });
+        let outgoing = core::mem::replace(&mut self.outgoing, outgoing);
+        self.retire_deck(outgoing);
+        if let Some(stinger) = transition.stinger {
+            let old = self.stinger.replace((stinger, 0.0));
+            self.retire_stinger(old.map(|(stinger, _)| stinger));
+        }
+        self.current = track.map(|track| Deck {
+            track,
+            t: -delay,
+            fade,
+        });
+    }
+}
+
+impl<T: Frame + Copy> Signal for MusicPlayer<T> {
+    type Frame = T;
+
+    fn sample(&self, interval: f32, out: &mut [T]) {
+        let state = &mut *self.state.borrow_mut();
+        if self.requests.refresh() {
+            if let Some(request) = unsafe { (*self.requests.received()).take() } {
+                state.schedule(request);
+            }
+        }
+
+        let interval = f64::from(interval);
+        for o in out {
+            if let Some((_, wait)) = state.pending {
+                if wait <= 0.0 {
+                    let (request, _) = state.pending.take().unwrap();
+                    state.execute(request);
+                }
+            }
+
+            *o = T::ZERO;
+            for deck in state.current.iter().chain(state.outgoing.iter()) {
+                *o = frame::mix(o, &deck.get());
+            }
+            if let Some((ref stinger, t)) = state.stinger {
+                *o = frame::mix(o, &stinger.interpolate(t * f64::from(stinger.rate())));
+            }
+
+            if let Some((_, ref mut wait)) = state.pending {
+                *wait -= interval;
+            }
+            if let Some(ref mut deck) = state.current {
+                if !deck.advance(interval) {
+                    let deck = state.current.take();
+                    state.retire_deck(deck);
+                }
+            }
+            if let Some(ref mut deck) = state.outgoing {
+                if !deck.advance(interval) || deck.fade.is_silent() {
+                    let deck = state.outgoing.take();
+                    state.retire_deck(deck);
+                }
+            }
+            if let Some((ref stinger, ref mut t)) = state.stinger {
+                *t += interval;
+                if *t >= stinger.runtime() {
+                    let stinger = state.stinger.take();
+                    state.retire_stinger(stinger.map(|(stinger, _)| stinger));
+                }
+            }
+        }
+    }
+}
+
+/// Thread-safe control for a [`MusicPlayer`]
+pub struct MusicPlayerControl<'a, T> {
+    requests: &'a Swap<Option<Request<T>>>,
+    free: &'a UnsafeCell<spsc::Receiver<Free<T>>>,
+}
+
+unsafe impl<'a, T: 'a> Controlled<'a> for MusicPlayer<T> {
+    type Control = MusicPlayerControl<'a, T>;
+
+    unsafe fn make_control(signal: &'a MusicPlayer<T>) -> Self::Control {
+        MusicPlayerControl {
+            requests: &signal.requests,
+            free: &signal.free,
+        }
+    }
+}
+
+impl<'a, T> MusicPlayerControl<'a, T> {
+    /// Switch to `track` according to `transition`, starting from its beginning
+    pub fn play(&mut self, track: MusicTrack<T>, transition: Transition<T>) {
+        self.send(Request {
+            track: Some(track),
+            transition,
+        });
+    }
+
+    /// Fall silent according to `transition`
+    pub fn stop(&mut self, transition: Transition<T>) {
+        self.send(Request {
+            track: None,
+            transition,
+        });
+    }
+
+    fn send(&mut self, request: Request<T>) {
+        self.gc();
+        unsafe {
+            *self.requests.pending() = Some(request);
+        }
+        self.requests.flush();
+    }
+
+    /// Free tracks and stingers the player no longer needs
+    fn gc(&mut self) {
+        let free = unsafe { &mut *self.free.get() };
+        free.update();
+        for _ in free.drain() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A looping track that outputs `value` at 60 BPM in 4/4, one sample per second
+    fn track(value: f32) -> MusicTrack<f32> {
+        MusicTrack::new(Frames::from_slice(1, &[value; 8]), 60.0, 4)
+    }
+
+    #[test]
+    fn sync_points() {
+        let mut t = track(1.0);
+        t.offset = 0.5;
+        t.markers = alloc::vec![2.0, 3.0];
+        assert_eq!(t.until(1.25, TransitionPoint::Immediate), 0.0);
+        assert_eq!(t.until(1.25, TransitionPoint::Beat), 0.25);
+        assert_eq!(t.until(1.25, TransitionPoint::Bar), 3.25);
+        assert_eq!(t.until(1.25, TransitionPoint::Marker), 0.75);
+        // Wrapping around
+        assert_eq!(t.until(5.0, TransitionPoint::Bar), 3.5);
+        assert_eq!(t.until(3.5, TransitionPoint::Marker), 6.5);
+        t.looping = false;
+        assert_eq!(t.until(3.5, TransitionPoint::Marker), 4.5);
+    }
+
+    #[test]
+    fn transition() {
+        let player = MusicPlayer::new();
+        let mut control = unsafe { MusicPlayer::make_control(&player) };
+        control.play(track(1.0), Transition::default());
+        let mut buf = [0.0; 6];
+        player.sample(0.5, &mut buf);
+        assert_eq!(buf, [1.0; 6]);
+
+        // Crossfade on the next bar, at 4 seconds
+        control.play(
+            track(3.0),
+            Transition {
+                point: TransitionPoint::Bar,
+                crossfade: 1.0,
+                ..Transition::default()
+            },
+        );
+        player.sample(0.5, &mut buf);
+        assert_eq!(buf, [1.0, 1.0, 1.0, 2.0, 3.0, 3.0]);
+        player.sample(0.5, &mut buf[..1]);
+
+        // Stinger on the next beat, at 2.5 seconds into the track, followed by the new track
+        control.play(
+            track(5.0),
+            Transition {
+                stinger: Some(Frames::from_slice(2, &[10.0; 2])),
+                ..Transition::default()
+            },
+        );
+        player.sample(0.5, &mut buf);
+        assert_eq!(buf, [3.0, 10.0, 10.0, 5.0, 5.0, 5.0]);
+    }
+
+    #[test]
+    fn free_on_control() {
+        let player = MusicPlayer::new();
+        let mut control = unsafe { MusicPlayer::make_control(&player) };
+        let frames = Frames::from_slice(1, &[1.0; 2]);
+        let mut t = MusicTrack::new(frames.clone(), 60.0, 4);
+        t.looping = false;
+        control.play(t, Transition::default());
+        let mut buf = [0.0; 4];
+        player.sample(1.0, &mut buf);
+        // The finished track is held until the control is next used
+        assert_eq!(Arc::strong_count(&frames), 2);
+        control.stop(Transition::default());
+        assert_eq!(Arc::strong_count(&frames), 1);
+    }
+}
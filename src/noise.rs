@@ -0,0 +1,249 @@
+//! Random noise generators
+//!
+//! Each generator is driven by an explicitly seeded pseudorandom number generator, so output is
+//! reproducible. Pink and brown noise are shaped by filters whose coefficients are derived from
+//! the sample rate, so their spectra are consistent across output sample rates.
+
+use core::cell::Cell;
+
+use crate::{math::Float, Sample, Seek, Signal};
+
+/// A [`Signal`] that produces uniformly distributed white noise between -1 and 1, forever
+pub struct WhiteNoise {
+    rng: Cell<Rng>,
+}
+
+impl WhiteNoise {
+    /// Construct a white noise generator, which always produces the same noise for the same `seed`
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Cell::new(Rng::new(seed)),
+        }
+    }
+}
+
+impl Signal for WhiteNoise {
+    type Frame = Sample;
+
+    fn sample(&self, _interval: f32, out: &mut [Sample]) {
+        let mut rng = self.rng.get();
+        for x in out {
+            *x = rng.bipolar();
+        }
+        self.rng.set(rng);
+    }
+}
+
+impl Seek for WhiteNoise {
+    /// Noise has no meaningful position, so seeking has no effect
+    fn seek(&self, _: f32) {}
+}
+
+/// A [`Signal`] that produces pink noise, forever
+///
+/// Pink noise has equal power in every octave, making it sound more balanced than white noise.
+/// Suitable for wind, rain, and surf. Output is approximately between -1 and 1.
+pub struct PinkNoise {
+    rng: Cell<Rng>,
+    /// States of the parallel one-pole filters that shape the spectrum
+    state: Cell<[f32; 7]>,
+}
+
+impl PinkNoise {
+    /// Construct a pink noise generator, which always produces the same noise for the same `seed`
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Cell::new(Rng::new(seed)),
+            state: Cell::new([0.0; 7]),
+        }
+    }
+}
+
+impl Signal for PinkNoise {
+    type Frame = Sample;
+
+    fn sample(&self, interval: f32, out: &mut [Sample]) {
+        // Paul Kellet's refined method, accurate to within 0.05 dB above 9.2 Hz at 44.1 kHz
+        let filters = PINK_FILTERS.map(|(pole, gain)| retune(pole, gain, interval));
+        let mut rng = self.rng.get();
+        let mut b = self.state.get();
+        for x in out {
+            let white = rng.bipolar();
+            for (b, &(pole, gain)) in b.iter_mut().zip(&filters) {
+                *b = pole * *b + white * gain;
+            }
+            // Corrections near the Nyquist frequency, which scale with the sample rate by nature
+            b[5] = -0.7616 * b[5] - white * 0.0168980;
+            let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+            b[6] = white * 0.115926;
+            *x = pink * 0.11;
+        }
+        self.rng.set(rng);
+        self.state.set(b);
+    }
+}
+
+impl Seek for PinkNoise {
+    /// Noise has no meaningful position, so seeking has no effect
+    fn seek(&self, _: f32) {}
+}
+
+/// A [`Signal`] that produces brown noise, forever
+///
+/// Brown noise is dominated by low frequencies, with power falling 6 dB per octave. Suitable for
+/// rumbles, distant thunder, and engine hum. Output is approximately between -1 and 1.
+pub struct BrownNoise {
+    rng: Cell<Rng>,
+    /// Leaky integral of white noise
+    state: Cell<f32>,
+}
+
+impl BrownNoise {
+    /// Construct a brown noise generator, which always produces the same noise for the same `seed`
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Cell::new(Rng::new(seed)),
+            state: Cell::new(0.0),
+        }
+    }
+}
+
+impl Signal for BrownNoise {
+    type Frame = Sample;
+
+    fn sample(&self, interval: f32, out: &mut [Sample]) {
+        // Leak slightly so the integral can't wander off
+        let (pole, gain) = retune(1.0 / 1.02, 0.02 / 1.02, interval);
+        let mut rng = self.rng.get();
+        let mut state = self.state.get();
+        for x in out {
+            state = pole * state + gain * rng.bipolar();
+            *x = state * 3.5;
+        }
+        self.rng.set(rng);
+        self.state.set(state);
+    }
+}
+
+impl Seek for BrownNoise {
+    /// Noise has no meaningful position, so seeking has no effect
+    fn seek(&self, _: f32) {}
+}
+
+/// Pole and gain of each of the one-pole low-pass filters in Paul Kellet's pink noise filter, at
+/// `REFERENCE_RATE`
+const PINK_FILTERS: [(f32, f32); 5] = [
+    (0.99886, 0.0555179),
+    (0.99332, 0.0750759),
+    (0.96900, 0.153852),
+    (0.86650, 0.3104856),
+    (0.55000, 0.5329522),
+];
+
+/// Sample rate, in Hz, at which the noise filters' coefficients were designed
+const REFERENCE_RATE: f32 = 44_100.0;
+
+/// Adapt a one-pole low-pass filter, `y = pole * y + gain * x`, designed at `REFERENCE_RATE` to a
+/// sample `interval`
+///
+/// The cutoff frequency and the level of the output spectrum below it are preserved. White noise
+/// spreads its power over a wider band at higher sample rates, so the gain compensates for that
+/// too.
+fn retune(pole: f32, gain: f32, interval: f32) -> (f32, f32) {
+    let scale = interval * REFERENCE_RATE;
+    let retuned = pole.powf(scale);
+    (
+        retuned,
+        gain * (1.0 - retuned) / (1.0 - pole) / scale.sqrt(),
+    )
+}
+
+/// Small, fast pseudorandom number generator (xorshift64*)
+///
+/// Not suitable for cryptography.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        // Scramble the seed with SplitMix64 so that similar seeds produce unrelated sequences, and
+        // so that the state is never zero
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        Self(if z == 0 { 1 } else { z })
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Uniformly distributed in [0, 1)
+    pub(crate) fn unit(&mut self) -> f32 {
+        // Use the high bits, which are the highest quality
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Uniformly distributed in [-1, 1)
+    pub(crate) fn bipolar(&mut self) -> f32 {
+        self.unit() * 2.0 - 1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Mean square of a signal's output, and of the difference between consecutive samples
+    fn power(signal: &impl Signal<Frame = Sample>, rate: f32) -> (f32, f32) {
+        let mut buf = [0.0; 4096];
+        signal.sample(1.0 / rate, &mut buf);
+        signal.sample(1.0 / rate, &mut buf);
+        let n = buf.len() as f32;
+        let total = buf.iter().map(|x| x * x).sum::<f32>() / n;
+        let high = buf.windows(2).map(|w| (w[1] - w[0]).powi(2)).sum::<f32>() / n;
+        (total, high)
+    }
+
+    #[test]
+    fn reproducible() {
+        let sample = |signal: &dyn Signal<Frame = Sample>| {
+            let mut buf = [0.0; 64];
+            signal.sample(1.0 / 44100.0, &mut buf);
+            buf
+        };
+        assert_eq!(sample(&WhiteNoise::new(1)), sample(&WhiteNoise::new(1)));
+        assert_ne!(sample(&WhiteNoise::new(1)), sample(&WhiteNoise::new(2)));
+        assert_eq!(sample(&PinkNoise::new(1)), sample(&PinkNoise::new(1)));
+        assert_eq!(sample(&BrownNoise::new(1)), sample(&BrownNoise::new(1)));
+    }
+
+    #[test]
+    fn spectral_tilt() {
+        // Proportion of power in high frequencies falls from white to pink to brown
+        let tilt = |(total, high): (f32, f32)| high / total;
+        let white = tilt(power(&WhiteNoise::new(0), 44100.0));
+        let pink = tilt(power(&PinkNoise::new(0), 44100.0));
+        let brown = tilt(power(&BrownNoise::new(0), 44100.0));
+        assert!(white > 1.5, "{}", white);
+        assert!(pink < white / 2.0, "{} {}", pink, white);
+        assert!(brown < pink / 2.0, "{} {}", brown, pink);
+    }
+
+    #[test]
+    fn sample_rate() {
+        // Halving the sample rate squares the pole, keeping the cutoff frequency
+        let (pole, gain) = retune(0.5, 0.25, 2.0 / REFERENCE_RATE);
+        assert!((pole - 0.25).abs() < 1e-6);
+        assert!((gain - 0.375 / 2.0f32.sqrt()).abs() < 1e-6);
+
+        // Level is consistent across sample rates
+        let (low, _) = power(&BrownNoise::new(0), 44100.0);
+        let (high, _) = power(&BrownNoise::new(0), 96000.0);
+        assert!((0.75..1.25).contains(&(high / low)), "{} {}", low, high);
+    }
+}
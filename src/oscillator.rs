@@ -0,0 +1,300 @@
+//! Band-limited classic waveforms
+//!
+//! Discontinuities in the naive waveforms are smoothed with polynomial approximations of
+//! band-limited steps (PolyBLEP) and ramps (PolyBLAMP), greatly reducing aliasing at little cost.
+
+use core::{
+    cell::Cell,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use crate::{math::Float, Controlled, Sample, Seek, Signal};
+
+/// A [`Signal`] that produces a band-limited square or pulse wave, forever
+///
+/// The output is 1 for the first `pulse_width` of each cycle and -1 for the remainder.
+pub struct Square {
+    phase: Phase,
+    pulse_width: AtomicU32,
+}
+
+impl Square {
+    /// Construct a pulse wave that begins `phase` cycles in, cycles `frequency_hz` times per
+    /// second, and is high for a `pulse_width` fraction of each cycle
+    ///
+    /// A `pulse_width` of 0.5 produces a square wave.
+    pub fn new(phase: f32, frequency_hz: f32, pulse_width: f32) -> Self {
+        Self {
+            phase: Phase::new(phase, frequency_hz),
+            pulse_width: AtomicU32::new(pulse_width.to_bits()),
+        }
+    }
+}
+
+impl Signal for Square {
+    type Frame = Sample;
+
+    fn sample(&self, interval: f32, out: &mut [Sample]) {
+        let width = f32::from_bits(self.pulse_width.load(Ordering::Relaxed)).clamp(0.0, 1.0);
+        self.phase.run(interval, out, |t, dt| {
+            let naive = if t < width { 1.0 } else { -1.0 };
+            naive + poly_blep(t, dt) - poly_blep((t - width).rem_euclid(1.0), dt)
+        });
+    }
+}
+
+impl Seek for Square {
+    fn seek(&self, seconds: f32) {
+        self.phase.seek(seconds);
+    }
+}
+
+/// Thread-safe control for a [`Square`] signal
+pub struct SquareControl<'a> {
+    frequency: &'a AtomicU32,
+    pulse_width: &'a AtomicU32,
+}
+
+unsafe impl<'a> Controlled<'a> for Square {
+    type Control = SquareControl<'a>;
+
+    unsafe fn make_control(signal: &'a Square) -> Self::Control {
+        SquareControl {
+            frequency: &signal.phase.frequency,
+            pulse_width: &signal.pulse_width,
+        }
+    }
+}
+
+impl<'a> SquareControl<'a> {
+    /// Get the current frequency in Hz
+    pub fn frequency(&self) -> f32 {
+        FrequencyControl(self.frequency).frequency()
+    }
+
+    /// Set the frequency in Hz
+    pub fn set_frequency(&mut self, frequency_hz: f32) {
+        FrequencyControl(self.frequency).set_frequency(frequency_hz);
+    }
+
+    /// Get the fraction of each cycle for which the output is high
+    pub fn pulse_width(&self) -> f32 {
+        f32::from_bits(self.pulse_width.load(Ordering::Relaxed))
+    }
+
+    /// Set the fraction of each cycle for which the output is high
+    pub fn set_pulse_width(&mut self, pulse_width: f32) {
+        self.pulse_width
+            .store(pulse_width.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// A [`Signal`] that produces a band-limited rising sawtooth wave, forever
+pub struct Saw {
+    phase: Phase,
+}
+
+impl Saw {
+    /// Construct a sawtooth wave that begins `phase` cycles in and cycles `frequency_hz` times per
+    /// second
+    pub fn new(phase: f32, frequency_hz: f32) -> Self {
+        Self {
+            phase: Phase::new(phase, frequency_hz),
+        }
+    }
+}
+
+impl Signal for Saw {
+    type Frame = Sample;
+
+    fn sample(&self, interval: f32, out: &mut [Sample]) {
+        self.phase
+            .run(interval, out, |t, dt| 2.0 * t - 1.0 - poly_blep(t, dt));
+    }
+}
+
+impl Seek for Saw {
+    fn seek(&self, seconds: f32) {
+        self.phase.seek(seconds);
+    }
+}
+
+unsafe impl<'a> Controlled<'a> for Saw {
+    type Control = FrequencyControl<'a>;
+
+    unsafe fn make_control(signal: &'a Saw) -> Self::Control {
+        FrequencyControl(&signal.phase.frequency)
+    }
+}
+
+/// A [`Signal`] that produces a band-limited triangle wave, forever
+///
+/// Each cycle rises from -1 to 1 and falls back again.
+pub struct Triangle {
+    phase: Phase,
+}
+
+impl Triangle {
+    /// Construct a triangle wave that begins `phase` cycles in and cycles `frequency_hz` times
+    /// per second
+    pub fn new(phase: f32, frequency_hz: f32) -> Self {
+        Self {
+            phase: Phase::new(phase, frequency_hz),
+        }
+    }
+}
+
+impl Signal for Triangle {
+    type Frame = Sample;
+
+    fn sample(&self, interval: f32, out: &mut [Sample]) {
+        self.phase.run(interval, out, |t, dt| {
+            let naive = 1.0 - 4.0 * (t - 0.5).abs();
+            // The slope changes by 8 at each corner
+            naive + 4.0 * dt * (poly_blamp(t, dt) - poly_blamp((t + 0.5) % 1.0, dt))
+        });
+    }
+}
+
+impl Seek for Triangle {
+    fn seek(&self, seconds: f32) {
+        self.phase.seek(seconds);
+    }
+}
+
+unsafe impl<'a> Controlled<'a> for Triangle {
+    type Control = FrequencyControl<'a>;
+
+    unsafe fn make_control(signal: &'a Triangle) -> Self::Control {
+        FrequencyControl(&signal.phase.frequency)
+    }
+}
+
+/// Thread-safe control for the frequency of a [`Saw`] or [`Triangle`] signal
+pub struct FrequencyControl<'a>(&'a AtomicU32);
+
+impl<'a> FrequencyControl<'a> {
+    /// Get the current frequency in Hz
+    pub fn frequency(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    /// Set the frequency in Hz
+    pub fn set_frequency(&mut self, frequency_hz: f32) {
+        self.0.store(frequency_hz.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Position within a cycle, advanced at a controllable rate
+struct Phase {
+    /// Fraction of a cycle, in [0, 1)
+    phase: Cell<f32>,
+    /// Cycles per second
+    frequency: AtomicU32,
+}
+
+impl Phase {
+    fn new(phase: f32, frequency_hz: f32) -> Self {
+        Self {
+            phase: Cell::new(phase.rem_euclid(1.0)),
+            frequency: AtomicU32::new(frequency_hz.to_bits()),
+        }
+    }
+
+    fn frequency(&self) -> f32 {
+        f32::from_bits(self.frequency.load(Ordering::Relaxed))
+    }
+
+    /// Fill `out` with `f(phase, cycles per sample)`, advancing the phase
+    fn run(&self, interval: f32, out: &mut [Sample], f: impl Fn(f32, f32) -> f32) {
+        let dt = self.frequency() * interval;
+        // Smoothing regions must not overlap
+        let smoothing = dt.abs().min(0.5);
+        let mut t = self.phase.get();
+        for x in out {
+            *x = f(t, smoothing);
+            t = (t + dt).rem_euclid(1.0);
+        }
+        self.phase.set(t);
+    }
+
+    fn seek(&self, seconds: f32) {
+        self.phase
+            .set((self.phase.get() + seconds * self.frequency()).rem_euclid(1.0));
+    }
+}
+
+/// Correction for a downward step of 2 at `t` = 0, where `dt` is the phase increment per sample
+fn poly_blep(t: f32, dt: f32) -> f32 {
+    if t < dt {
+        let x = t / dt;
+        2.0 * x - x * x - 1.0
+    } else if t > 1.0 - dt {
+        let x = (t - 1.0) / dt;
+        x * x + 2.0 * x + 1.0
+    } else {
+        0.0
+    }
+}
+
+/// Correction for an increase in slope of 2 per sample at `t` = 0, where `dt` is the phase
+/// increment per sample
+fn poly_blamp(t: f32, dt: f32) -> f32 {
+    if t < dt {
+        let x = t / dt - 1.0;
+        -x * x * x / 3.0
+    } else if t > 1.0 - dt {
+        let x = (t - 1.0) / dt + 1.0;
+        x * x * x / 3.0
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::f32::consts::TAU;
+
+    const RATE: f32 = 48000.0;
+    const FREQUENCY: f32 = 1700.0;
+
+    /// Magnitude of the alias of the 21st harmonic in `signal`, a function of phase
+    fn alias(signal: impl Fn(f32) -> f32) -> f32 {
+        // 21 * 1700 Hz aliases to 12300 Hz, far from any true harmonic
+        let alias = 21.0 * FREQUENCY - RATE;
+        let n = 4800;
+        let (mut re, mut im) = (0.0, 0.0);
+        for i in 0..n {
+            let phase = (i as f32 * FREQUENCY / RATE) % 1.0;
+            let window = 0.5 - 0.5 * (TAU * i as f32 / n as f32).cos();
+            let x = window * signal(phase);
+            let angle = TAU * ((i as f32 * alias / RATE) % 1.0);
+            re += x * angle.cos();
+            im += x * angle.sin();
+        }
+        (re * re + im * im).sqrt()
+    }
+
+    /// Sample a single frame of `signal`
+    fn at(signal: &impl Signal<Frame = Sample>) -> f32 {
+        let mut buf = [0.0];
+        signal.sample(1.0 / RATE, &mut buf);
+        buf[0]
+    }
+
+    #[test]
+    fn band_limited() {
+        let naive = alias(|t| 2.0 * t - 1.0);
+        let smooth = alias(|t| at(&Saw::new(t, FREQUENCY)));
+        assert!(smooth < naive / 8.0, "saw: {} vs {}", smooth, naive);
+
+        let naive = alias(|t| if t < 0.5 { 1.0 } else { -1.0 });
+        let smooth = alias(|t| at(&Square::new(t, FREQUENCY, 0.5)));
+        assert!(smooth < naive / 8.0, "square: {} vs {}", smooth, naive);
+
+        let naive = alias(|t| 1.0 - 4.0 * (t - 0.5).abs());
+        let smooth = alias(|t| at(&Triangle::new(t, FREQUENCY)));
+        assert!(smooth < naive / 8.0, "triangle: {} vs {}", smooth, naive);
+    }
+}
@@ -0,0 +1,394 @@
+use alloc::boxed::Box;
+use core::cell::RefCell;
+
+use crate::{
+    biquad::CHUNK_SIZE, compressor::MAX_SAMPLE_RATE, frame, math::Float, ring::Ring, swap::Swap,
+    Controlled, Filter, Sample, Signal, Smoothed,
+};
+
+/// Simulates the reflections of a room, using Jezar Wakefield's Freeverb design
+///
+/// Each channel is passed through eight parallel comb filters with damped feedback, followed by
+/// four series allpass filters, with slightly different delays between channels for a wide stereo
+/// image. Changes to room size and pre-delay are glided over to avoid clicks.
+///
+/// To use a single reverb for many sounds, e.g. all those in a particular environment, wrap a
+/// [`Mixer`](crate::Mixer) in a fully wet `Reverb` and play the sounds into that. To reverberate a
+/// single signal, or the output of an entire mix, wrap it directly.
+pub struct Reverb<T: ?Sized> {
+    options: Swap<ReverbOptions>,
+    state: RefCell<State>,
+    inner: T,
+}
+
+struct State {
+    pre_delay: Ring<[Sample; 2]>,
+    channels: [Channel; 2],
+    /// Proportion of the maximum room size to which delays are scaled
+    scale: Smoothed<f32>,
+    /// Pre-delay in seconds
+    pre_delay_time: Smoothed<f32>,
+}
+
+struct Channel {
+    combs: Box<[Comb]>,
+    allpasses: Box<[Allpass]>,
+}
+
+impl Channel {
+    fn new(spread: f32) -> Self {
+        Self {
+            combs: COMBS.iter().map(|&t| Comb::new(t + spread)).collect(),
+            allpasses: ALLPASSES
+                .iter()
+                .map(|&t| Allpass::new(t + spread))
+                .collect(),
+        }
+    }
+}
+
+/// Feedback comb filter with a low-pass filter in the loop
+struct Comb {
+    ring: Ring,
+    /// Delay at the maximum room size, in seconds
+    time: f32,
+    /// Delay at the current room size, in samples
+    delay: f32,
+    /// Feedback gain at the current room size and decay
+    feedback: f32,
+    /// Low-pass filter state
+    store: f32,
+}
+
+impl Comb {
+    fn new(time: f32) -> Self {
+        Self {
+            ring: Ring::new((time * MAX_SAMPLE_RATE).ceil() as usize + 2),
+            time,
+            delay: 1.0,
+            feedback: 0.0,
+            store: 0.0,
+        }
+    }
+
+    fn max_delay(&self) -> f32 {
+        (self.ring.capacity() - 2) as f32
+    }
+
+    fn process(&mut self, x: f32, damping: f32) -> f32 {
+        let y = self.ring.sample(1, -self.delay);
+        self.store = y + damping * (self.store - y);
+        self.ring.push(x + self.store * self.feedback);
+        y
+    }
+}
+
+/// Schroeder allpass filter, as approximated by Freeverb
+struct Allpass {
+    ring: Ring,
+    /// Delay at the maximum room size, in seconds
+    time: f32,
+    /// Delay at the current room size, in samples
+    delay: f32,
+}
+
+impl Allpass {
+    fn new(time: f32) -> Self {
+        Self {
+            ring: Ring::new((time * MAX_SAMPLE_RATE).ceil() as usize + 2),
+            time,
+            delay: 1.0,
+        }
+    }
+
+    fn max_delay(&self) -> f32 {
+        (self.ring.capacity() - 2) as f32
+    }
+
+    fn process(&mut self, x: f32) -> f32 {
+        let y = self.ring.sample(1, -self.delay);
+        self.ring.push(x + y * 0.5);
+        y - x
+    }
+}
+
+impl<T: ?Sized> Reverb<T> {
+    /// Maximum pre-delay, in seconds
+    pub const MAX_PRE_DELAY: f32 = 0.25;
+}
+
+impl<T> Reverb<T> {
+    /// Apply reverb to `signal`
+    pub fn new(signal: T, options: ReverbOptions) -> Self {
+        Self {
+            options: Swap::new(options),
+            state: RefCell::new(State {
+                pre_delay: Ring::new((Self::MAX_PRE_DELAY * MAX_SAMPLE_RATE).ceil() as usize + 2),
+                channels: [Channel::new(0.0), Channel::new(STEREO_SPREAD)],
+                scale: Smoothed::new(options.scale()),
+                pre_delay_time: Smoothed::new(options.pre_delay.min(Self::MAX_PRE_DELAY)),
+            }),
+            inner: signal,
+        }
+    }
+}
+
+/// Configuration for a [`Reverb`] filter
+#[derive(Debug, Copy, Clone)]
+pub struct ReverbOptions {
+    /// Size of the simulated space, from 0 to 1. Larger rooms have more widely spaced reflections.
+    pub room_size: f32,
+    /// Seconds for the reverberation to decay by 60 dB
+    pub decay: f32,
+    /// Attenuation of high frequencies in each reflection, from 0 to 1
+    pub damping: f32,
+    /// Seconds between the dry signal and the onset of reverberation. Limited to
+    /// [`Reverb::MAX_PRE_DELAY`].
+    pub pre_delay: f32,
+    /// Stereo width of the reverberation, from 0 (mono) to 1
+    pub width: f32,
+    /// Proportion of the output made up of reverberation, from 0 (dry) to 1 (wet). Use 1 when
+    /// reverberating a send.
+    pub mix: f32,
+}
+
+impl ReverbOptions {
+    /// A small, moderately damped room
+    pub const ROOM: Self = Self {
+        room_size: 0.4,
+        decay: 0.6,
+        damping: 0.5,
+        pre_delay: 0.005,
+        width: 1.0,
+        mix: 0.25,
+    };
+
+    /// A large concert hall with a long, warm tail
+    pub const HALL: Self = Self {
+        room_size: 0.9,
+        decay: 2.5,
+        damping: 0.4,
+        pre_delay: 0.025,
+        width: 1.0,
+        mix: 0.3,
+    };
+
+    /// A dense, bright plate reverb
+    pub const PLATE: Self = Self {
+        room_size: 0.6,
+        decay: 1.6,
+        damping: 0.1,
+        pre_delay: 0.0,
+        width: 1.0,
+        mix: 0.3,
+    };
+
+    /// A vast stone space with a very long tail
+    pub const CATHEDRAL: Self = Self {
+        room_size: 1.0,
+        decay: 6.0,
+        damping: 0.3,
+        pre_delay: 0.05,
+        width: 1.0,
+        mix: 0.35,
+    };
+
+    /// Proportion of the maximum delays to use
+    fn scale(&self) -> f32 {
+        0.3 + 0.7 * self.room_size.clamp(0.0, 1.0)
+    }
+}
+
+impl Default for ReverbOptions {
+    fn default() -> Self {
+        Self::ROOM
+    }
+}
+
+impl<T: Signal<Frame = [Sample; 2]> + ?Sized> Signal for Reverb<T> {
+    type Frame = [Sample; 2];
+
+    #[allow(clippy::float_cmp)]
+    fn sample(&self, interval: f32, out: &mut [[Sample; 2]]) {
+        self.inner.sample(interval, out);
+        self.options.refresh();
+        let options = unsafe { *self.options.received() };
+        let state = &mut *self.state.borrow_mut();
+
+        let scale = options.scale();
+        if state.scale.get() != scale {
+            state.scale.set(scale);
+        }
+        let pre_delay = options.pre_delay.min(Reverb::<T>::MAX_PRE_DELAY);
+        if state.pre_delay_time.get() != pre_delay {
+            state.pre_delay_time.set(pre_delay);
+        }
+        // Freeverb's damping, adjusted to give the same time constant at any sample rate
+        let damping = (options.damping.clamp(0.0, 1.0) * 0.4).powf(REFERENCE_RATE * interval);
+        let wet1 = WET_GAIN * (0.5 + options.width / 2.0);
+        let wet2 = WET_GAIN * (0.5 - options.width / 2.0);
+        let max_pre_delay = (state.pre_delay.capacity() - 2) as f32;
+
+        for chunk in out.chunks_mut(CHUNK_SIZE) {
+            let scale = state.scale.get();
+            for channel in &mut state.channels {
+                for comb in channel.combs.iter_mut() {
+                    let time = comb.time * scale;
+                    comb.delay = (time / interval).clamp(1.0, comb.max_delay());
+                    comb.feedback = 10.0f32.powf(-3.0 * time / options.decay);
+                }
+                for allpass in channel.allpasses.iter_mut() {
+                    allpass.delay =
+                        (allpass.time * scale / interval).clamp(1.0, allpass.max_delay());
+                }
+            }
+
+            for x in chunk.iter_mut() {
+                // The frame just pushed lies one sample behind the write cursor
+                state.pre_delay.push(*x);
+                let delay = (state.pre_delay_time.get() / interval + 1.0).min(max_pre_delay);
+                let delayed = state.pre_delay.sample(1, -delay);
+                let input = (delayed[0] + delayed[1]) * INPUT_GAIN;
+
+                let mut wet = [0.0; 2];
+                for (channel, wet) in state.channels.iter_mut().zip(&mut wet) {
+                    *wet = channel
+                        .combs
+                        .iter_mut()
+                        .map(|comb| comb.process(input, damping))
+                        .sum();
+                    for allpass in channel.allpasses.iter_mut() {
+                        *wet = allpass.process(*wet);
+                    }
+                }
+                let wet = [wet[0] * wet1 + wet[1] * wet2, wet[1] * wet1 + wet[0] * wet2];
+                *x = frame::lerp(x, &wet, options.mix);
+
+                state.scale.advance(interval / SMOOTHING_PERIOD);
+                state.pre_delay_time.advance(interval / SMOOTHING_PERIOD);
+            }
+        }
+    }
+
+    fn remaining(&self) -> f32 {
+        self.options.refresh();
+        let options = unsafe { &*self.options.received() };
+        self.inner.remaining() + options.pre_delay.min(Reverb::<T>::MAX_PRE_DELAY) + options.decay
+    }
+
+    #[inline]
+    fn handle_dropped(&self) {
+        self.inner.handle_dropped();
+    }
+}
+
+impl<T: ?Sized> Filter for Reverb<T> {
+    type Inner = T;
+    fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+/// Thread-safe control for a [`Reverb`] filter
+pub struct ReverbControl<'a>(&'a Swap<ReverbOptions>);
+
+unsafe impl<'a, T: 'a> Controlled<'a> for Reverb<T> {
+    type Control = ReverbControl<'a>;
+
+    unsafe fn make_control(signal: &'a Reverb<T>) -> Self::Control {
+        ReverbControl(&signal.options)
+    }
+}
+
+impl<'a> ReverbControl<'a> {
+    /// Replace the filter's configuration, e.g. with a different preset
+    pub fn set_options(&mut self, options: ReverbOptions) {
+        unsafe {
+            *self.0.pending() = options;
+        }
+        self.0.flush();
+    }
+}
+
+/// Sample rate for which Freeverb was tuned
+const REFERENCE_RATE: f32 = 44_100.0;
+
+/// Comb filter delays at the maximum room size, in seconds. Freeverb's tunings, at its reference
+/// rate.
+const COMBS: [f32; 8] = [
+    1116.0 / REFERENCE_RATE,
+    1188.0 / REFERENCE_RATE,
+    1277.0 / REFERENCE_RATE,
+    1356.0 / REFERENCE_RATE,
+    1422.0 / REFERENCE_RATE,
+    1491.0 / REFERENCE_RATE,
+    1557.0 / REFERENCE_RATE,
+    1617.0 / REFERENCE_RATE,
+];
+
+/// Allpass filter delays at the maximum room size, in seconds
+const ALLPASSES: [f32; 4] = [
+    556.0 / REFERENCE_RATE,
+    441.0 / REFERENCE_RATE,
+    341.0 / REFERENCE_RATE,
+    225.0 / REFERENCE_RATE,
+];
+
+/// Seconds added to the right channel's delays to decorrelate it from the left
+const STEREO_SPREAD: f32 = 23.0 / REFERENCE_RATE;
+
+const INPUT_GAIN: f32 = 0.015;
+const WET_GAIN: f32 = 3.0;
+
+/// Seconds over which changes to room size and pre-delay are glided
+const SMOOTHING_PERIOD: f32 = 0.1;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Frames, FramesSignal};
+
+    fn impulse() -> FramesSignal<[Sample; 2]> {
+        FramesSignal::new(Frames::from_slice(48_000, &[[1.0, 1.0]]), 0.0)
+    }
+
+    fn rms(buf: &[[Sample; 2]]) -> f32 {
+        (buf.iter().map(|x| x[0] * x[0]).sum::<f32>() / buf.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn pre_delay() {
+        let options = ReverbOptions {
+            pre_delay: 0.01,
+            mix: 1.0,
+            ..ReverbOptions::ROOM
+        };
+        let s = Reverb::new(impulse(), options);
+        let mut buf = [[0.0; 2]; 2400];
+        s.sample(1.0 / 48_000.0, &mut buf);
+        // Nothing is heard until the pre-delay and the shortest comb delay have elapsed
+        let onset = 480 + (COMBS[0] * options.scale() * 48_000.0) as usize;
+        assert!(buf[..onset].iter().all(|x| x[0] == 0.0 && x[1] == 0.0));
+        assert!(buf[onset..].iter().any(|x| x[0] != 0.0));
+    }
+
+    #[test]
+    fn decay() {
+        let options = ReverbOptions {
+            decay: 1.0,
+            mix: 1.0,
+            ..ReverbOptions::HALL
+        };
+        let s = Reverb::new(impulse(), options);
+        let mut early = [[0.0; 2]; 4800];
+        s.sample(1.0 / 48_000.0, &mut early);
+        // The dry signal is over, but the tail continues
+        assert!((s.remaining() - 0.925).abs() < 1e-3);
+        let mut late = [[0.0; 2]; 48_000];
+        s.sample(1.0 / 48_000.0, &mut late);
+        s.sample(1.0 / 48_000.0, &mut late[..4800]);
+        // A second later, the level has fallen by about 60 dB
+        let fall = 20.0 * (rms(&early) / rms(&late[..4800])).log10();
+        assert!((fall - 60.0).abs() < 6.0, "{}", fall);
+    }
+}
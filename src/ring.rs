@@ -1,21 +1,21 @@
-use crate::{frame, math::Float, Sample, Signal};
-use alloc::{boxed::Box, vec};
+use crate::{frame, math::Float, Frame, Sample, Signal};
+use alloc::boxed::Box;
 
-pub struct Ring {
-    buffer: Box<[Sample]>,
+pub struct Ring<T = Sample> {
+    buffer: Box<[T]>,
     write: f32,
 }
 
-impl Ring {
+impl<T: Frame + Copy> Ring<T> {
     pub fn new(capacity: usize) -> Self {
         Self {
-            buffer: vec![0.0; capacity].into(),
+            buffer: (0..capacity).map(|_| T::ZERO).collect(),
             write: 0.0,
         }
     }
 
     /// Fill buffer from `signal`
-    pub fn write<S: Signal<Frame = Sample> + ?Sized>(&mut self, signal: &S, rate: u32, dt: f32) {
+    pub fn write<S: Signal<Frame = T> + ?Sized>(&mut self, signal: &S, rate: u32, dt: f32) {
         debug_assert!(
             dt * rate as f32 <= self.buffer.len() as f32,
             "output range exceeds capacity"
@@ -41,9 +41,24 @@ impl Ring {
         self.write = (self.write + rate as f32 * dt) % self.buffer.len() as f32;
     }
 
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Write a single frame at the write cursor, which must lie on a sample, and advance it
+    pub fn push(&mut self, x: T) {
+        debug_assert!(
+            self.write.fract() == 0.0,
+            "write cursor must lie on a sample"
+        );
+        let index = self.write as usize;
+        self.buffer[index] = x;
+        self.write = ((index + 1) % self.buffer.len()) as f32;
+    }
+
     /// Get the recorded signal at a certain sample, relative to the *write* cursor. `t` must be
     /// negative.
-    pub fn sample(&self, rate: u32, t: f32) -> f32 {
+    pub fn sample(&self, rate: u32, t: f32) -> T {
         debug_assert!(t < 0.0, "samples must lie in the past");
         debug_assert!(
             ((t * rate as f32).abs().ceil() as usize) < self.buffer.len(),
@@ -51,18 +66,15 @@ impl Ring {
         );
         let s = (self.write + t * rate as f32).rem_euclid(self.buffer.len() as f32);
         let x0 = s.trunc() as usize;
-        let fract = s.fract() as f32;
+        let fract = s.fract();
         let x1 = x0 + 1;
         let a = self.get(x0);
         let b = self.get(x1);
         frame::lerp(&a, &b, fract)
     }
 
-    fn get(&self, sample: usize) -> f32 {
-        if sample >= self.buffer.len() {
-            return 0.0;
-        }
-        self.buffer[sample]
+    fn get(&self, sample: usize) -> T {
+        self.buffer[sample % self.buffer.len()]
     }
 }
 
@@ -79,7 +91,7 @@ mod tests {
         fn sample(&self, interval: f32, out: &mut [Sample]) {
             for x in out {
                 let t = self.0.get();
-                *x = t as f32;
+                *x = t;
                 self.0.set(t + interval);
             }
         }
@@ -99,6 +111,18 @@ mod tests {
         assert_eq!(r.buffer[..], [1.0, 2.0, 3.0, 0.0]);
     }
 
+    #[test]
+    fn interpolate_across_end() {
+        // As when a spatial source's propagation delay places it between the last and first
+        // samples of the buffer
+        let mut r = Ring::new(4);
+        let s = TimeSignal(Cell::new(1.0));
+        r.write(&s, 1, 4.0);
+        assert_eq!(r.buffer[..], [1.0, 2.0, 3.0, 4.0]);
+        r.write(&s, 1, 1.0);
+        assert_eq!(r.sample(1, -1.5), 4.5);
+    }
+
     #[test]
     fn wrap() {
         let mut r = Ring::new(4);
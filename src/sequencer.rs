@@ -0,0 +1,356 @@
+use alloc::{boxed::Box, sync::Arc};
+use core::{
+    cell::Cell,
+    sync::atomic::{AtomicIsize, AtomicU32, Ordering},
+};
+
+use crate::{frame, Controlled, Frame, Frames, Seek, Signal};
+
+/// A [`Signal`] that triggers samples on a repeating step grid, forever
+///
+/// Each track plays one sample according to a pattern of steps, where bit `i` of a pattern
+/// enables step `i`. Steps are evenly divided into beats, with every other step optionally delayed
+/// to produce swing. Triggers are timed to a fraction of a sample, and the tempo, swing, and
+/// patterns can all be changed during playback through a [`SequencerControl`].
+///
+/// Tracks are monophonic: triggering a track that's still playing restarts its sample.
+pub struct Sequencer<T> {
+    tracks: Box<[Track<T>]>,
+    /// Pattern of each track
+    patterns: Box<[AtomicU32]>,
+    steps: u32,
+    steps_per_beat: u32,
+    bpm: AtomicU32,
+    swing: AtomicU32,
+    /// Beats elapsed since playback began
+    beat: Cell<f64>,
+    /// Index of the next step to be triggered, counting from the start of playback
+    next: Cell<u64>,
+    /// Approximation of `beat` in ticks, for reading from the control. Like
+    /// [`FramesSignal`](crate::FramesSignal), we avoid `AtomicU64` for portability.
+    ticks: AtomicIsize,
+}
+
+struct Track<T> {
+    frames: Arc<Frames<T>>,
+    /// Seconds into `frames` of the hit being played, if any
+    cursor: Cell<Option<f64>>,
+}
+
+impl<T> Sequencer<T> {
+    /// Maximum number of steps in a pattern
+    pub const MAX_STEPS: u32 = 32;
+
+    /// Construct a sequencer with a track for each of `samples`, all initially silent
+    ///
+    /// # Panics
+    ///
+    /// Panics if `options.steps` is zero or exceeds [`MAX_STEPS`](Self::MAX_STEPS), or if
+    /// `options.steps_per_beat` is zero.
+    pub fn new(samples: &[Arc<Frames<T>>], options: SequencerOptions) -> Self {
+        assert!(
+            (1..=Self::MAX_STEPS).contains(&options.steps),
+            "patterns must have between 1 and {} steps",
+            Self::MAX_STEPS
+        );
+        assert!(options.steps_per_beat != 0, "beats must contain steps");
+        Self {
+            tracks: samples
+                .iter()
+                .map(|frames| Track {
+                    frames: frames.clone(),
+                    cursor: Cell::new(None),
+                })
+                .collect(),
+            patterns: samples.iter().map(|_| AtomicU32::new(0)).collect(),
+            steps: options.steps,
+            steps_per_beat: options.steps_per_beat,
+            bpm: AtomicU32::new(options.bpm.to_bits()),
+            swing: AtomicU32::new(options.swing.to_bits()),
+            beat: Cell::new(0.0),
+            next: Cell::new(0),
+            ticks: AtomicIsize::new(0),
+        }
+    }
+
+    /// Tempo, treating anything but a positive number as stopped
+    fn bpm(&self) -> f32 {
+        f32::from_bits(self.bpm.load(Ordering::Relaxed)).max(0.0)
+    }
+
+    /// Beat at which step `index`, counting from the start of playback, is triggered
+    fn trigger_beat(&self, index: u64, swing: f64) -> f64 {
+        let delay = if index % 2 == 1 { swing } else { 0.0 };
+        (index as f64 + delay) / f64::from(self.steps_per_beat)
+    }
+
+    fn publish(&self) {
+        self.ticks.store(
+            (self.beat.get() * TICKS_PER_BEAT) as isize,
+            Ordering::Relaxed,
+        );
+    }
+}
+
+/// Configuration for a [`Sequencer`]
+#[derive(Debug, Copy, Clone)]
+pub struct SequencerOptions {
+    /// Tempo, in beats per minute. Zero or less stops the sequence, letting triggered samples
+    /// finish.
+    pub bpm: f32,
+    /// Delay of every other step, as a fraction of a step, e.g. 1/3 for triplet swing
+    pub swing: f32,
+    /// Number of steps in each pattern
+    pub steps: u32,
+    /// Number of steps in each beat
+    pub steps_per_beat: u32,
+}
+
+impl Default for SequencerOptions {
+    fn default() -> Self {
+        Self {
+            bpm: 120.0,
+            swing: 0.0,
+            steps: 16,
+            steps_per_beat: 4,
+        }
+    }
+}
+
+impl<T: Frame + Copy> Signal for Sequencer<T> {
+    type Frame = T;
+
+    fn sample(&self, interval: f32, out: &mut [T]) {
+        let beats_per_second = f64::from(self.bpm()) / 60.0;
+        let swing = f64::from(f32::from_bits(self.swing.load(Ordering::Relaxed))).clamp(0.0, 1.0);
+        let interval = f64::from(interval);
+        let mut beat = self.beat.get();
+        let mut next = self.next.get();
+        for o in out {
+            // Trigger steps that are due, offset by however far we've overshot them
+            loop {
+                let trigger = self.trigger_beat(next, swing);
+                // Nothing comes due while stopped
+                if trigger > beat || beats_per_second == 0.0 {
+                    break;
+                }
+                let step = (next % u64::from(self.steps)) as u32;
+                let offset = (beat - trigger) / beats_per_second;
+                for (track, pattern) in self.tracks.iter().zip(self.patterns.iter()) {
+                    if pattern.load(Ordering::Relaxed) & (1 << step) != 0 {
+                        track.cursor.set(Some(offset));
+                    }
+                }
+                next += 1;
+            }
+
+            *o = T::ZERO;
+            for track in self.tracks.iter() {
+                let t = match track.cursor.get() {
+                    Some(t) => t,
+                    None => continue,
+                };
+                let rate = f64::from(track.frames.rate());
+                *o = frame::mix(o, &track.frames.interpolate(t * rate));
+                let t = t + interval;
+                track.cursor.set(if t < track.frames.runtime() {
+                    Some(t)
+                } else {
+                    None
+                });
+            }
+            beat += interval * beats_per_second;
+        }
+        self.beat.set(beat);
+        self.next.set(next);
+        self.publish();
+    }
+}
+
+impl<T: Frame + Copy> Seek for Sequencer<T> {
+    /// Advances hits in progress and the beat position; steps skipped over are not triggered
+    fn seek(&self, seconds: f32) {
+        let seconds = f64::from(seconds);
+        for track in self.tracks.iter() {
+            let t = track.cursor.get().map(|t| t + seconds);
+            track.cursor.set(t.filter(|&t| t < track.frames.runtime()));
+        }
+        let swing = f64::from(f32::from_bits(self.swing.load(Ordering::Relaxed))).clamp(0.0, 1.0);
+        let beat = (self.beat.get() + seconds * f64::from(self.bpm()) / 60.0).max(0.0);
+        // `beat` is nonnegative, so truncation rounds down
+        let mut next = (beat * f64::from(self.steps_per_beat)) as u64;
+        while self.trigger_beat(next, swing) < beat {
+            next += 1;
+        }
+        self.beat.set(beat);
+        self.next.set(next);
+        self.publish();
+    }
+}
+
+/// Thread-safe control for a [`Sequencer`]
+pub struct SequencerControl<'a> {
+    patterns: &'a [AtomicU32],
+    steps: u32,
+    steps_per_beat: u32,
+    bpm: &'a AtomicU32,
+    swing: &'a AtomicU32,
+    ticks: &'a AtomicIsize,
+}
+
+unsafe impl<'a, T: 'a> Controlled<'a> for Sequencer<T> {
+    type Control = SequencerControl<'a>;
+
+    unsafe fn make_control(signal: &'a Sequencer<T>) -> Self::Control {
+        SequencerControl {
+            patterns: &signal.patterns,
+            steps: signal.steps,
+            steps_per_beat: signal.steps_per_beat,
+            bpm: &signal.bpm,
+            swing: &signal.swing,
+            ticks: &signal.ticks,
+        }
+    }
+}
+
+impl<'a> SequencerControl<'a> {
+    /// Get the current tempo in beats per minute
+    pub fn bpm(&self) -> f32 {
+        f32::from_bits(self.bpm.load(Ordering::Relaxed))
+    }
+
+    /// Set the tempo in beats per minute
+    ///
+    /// The beat position is preserved, so the change takes effect smoothly. Zero or less stops the
+    /// sequence until a positive tempo is set.
+    pub fn set_bpm(&mut self, bpm: f32) {
+        self.bpm.store(bpm.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Get the current swing
+    pub fn swing(&self) -> f32 {
+        f32::from_bits(self.swing.load(Ordering::Relaxed))
+    }
+
+    /// Set the delay of every other step, as a fraction of a step
+    pub fn set_swing(&mut self, swing: f32) {
+        self.swing.store(swing.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Number of tracks
+    pub fn tracks(&self) -> usize {
+        self.patterns.len()
+    }
+
+    /// Number of steps in each pattern
+    pub fn steps(&self) -> u32 {
+        self.steps
+    }
+
+    /// Get the pattern of `track`, where bit `i` is set if step `i` is enabled
+    pub fn pattern(&self, track: usize) -> u32 {
+        self.patterns[track].load(Ordering::Relaxed)
+    }
+
+    /// Replace the pattern of `track`, where bit `i` enables step `i`
+    ///
+    /// Bits beyond the number of steps are ignored.
+    pub fn set_pattern(&mut self, track: usize, pattern: u32) {
+        self.patterns[track].store(pattern, Ordering::Relaxed);
+    }
+
+    /// Enable or disable a single `step` of `track`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `step` is not less than [`steps`](Self::steps).
+    pub fn set_step(&mut self, track: usize, step: u32, enabled: bool) {
+        assert!(step < self.steps, "no such step");
+        if enabled {
+            self.patterns[track].fetch_or(1 << step, Ordering::Relaxed);
+        } else {
+            self.patterns[track].fetch_and(!(1 << step), Ordering::Relaxed);
+        }
+    }
+
+    /// Get the number of beats elapsed since playback began, as of the most recently sampled
+    /// block
+    ///
+    /// Accurate to 1/960th of a beat. Useful for synchronizing game events to the music.
+    pub fn beat(&self) -> f64 {
+        self.ticks.load(Ordering::Relaxed) as f64 / TICKS_PER_BEAT
+    }
+
+    /// Get the index of the step most recently reached, as of the most recently sampled block,
+    /// ignoring swing
+    pub fn step(&self) -> u32 {
+        let steps = self.ticks.load(Ordering::Relaxed) as u64 * u64::from(self.steps_per_beat)
+            / TICKS_PER_BEAT as u64;
+        (steps % u64::from(self.steps)) as u32
+    }
+}
+
+/// Resolution of the beat position reported to controls
+const TICKS_PER_BEAT: f64 = 960.0;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hits(buf: &[f32]) -> alloc::vec::Vec<usize> {
+        buf.iter()
+            .enumerate()
+            .filter(|&(_, &x)| x != 0.0)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    #[test]
+    fn steps() {
+        // One step per second, each triggering a sample lasting less than an output frame
+        let options = SequencerOptions {
+            bpm: 60.0,
+            swing: 0.0,
+            steps: 4,
+            steps_per_beat: 1,
+        };
+        let s = Sequencer::new(&[Frames::from_slice(100, &[1.0])], options);
+        let mut control = unsafe { Sequencer::make_control(&s) };
+        control.set_pattern(0, 0b1111);
+        let mut buf = [0.0; 16];
+        s.sample(0.25, &mut buf);
+        assert_eq!(hits(&buf), [0, 4, 8, 12]);
+        assert_eq!(control.beat(), 4.0);
+        assert_eq!(control.step(), 0);
+
+        control.set_step(0, 1, false);
+        control.set_step(0, 3, false);
+        s.sample(0.25, &mut buf);
+        assert_eq!(hits(&buf), [0, 8]);
+
+        // Zero tempo stops the sequence without triggering anything
+        control.set_bpm(0.0);
+        s.sample(0.25, &mut buf);
+        assert_eq!(hits(&buf), []);
+        assert_eq!(control.beat(), 8.0);
+        control.set_bpm(60.0);
+        s.sample(0.25, &mut buf);
+        assert_eq!(hits(&buf), [0, 8]);
+    }
+
+    #[test]
+    fn swing() {
+        let options = SequencerOptions {
+            bpm: 60.0,
+            swing: 0.5,
+            steps: 2,
+            steps_per_beat: 1,
+        };
+        let s = Sequencer::new(&[Frames::from_slice(100, &[1.0])], options);
+        let mut control = unsafe { Sequencer::make_control(&s) };
+        control.set_pattern(0, 0b11);
+        let mut buf = [0.0; 8];
+        s.sample(0.25, &mut buf);
+        assert_eq!(hits(&buf), [0, 6]);
+    }
+}
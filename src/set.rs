@@ -1,7 +1,11 @@
-use alloc::{collections::vec_deque::VecDeque, vec::Vec};
-use core::{cell::UnsafeCell, mem, ops::Deref};
+use alloc::{
+    collections::{btree_map::BTreeMap, vec_deque::VecDeque},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{cell::UnsafeCell, cmp::Reverse, mem, ops::Deref};
 
-use crate::spsc;
+use crate::{spsc, Command};
 
 /// Build a set
 pub fn set<T>() -> (SetHandle<T>, Set<T>) {
@@ -14,6 +18,8 @@ pub fn set<T>() -> (SetHandle<T>, Set<T>) {
         old_senders: VecDeque::new(),
         signal_capacity: INITIAL_SIGNALS_CAPACITY,
         active_signals: 0,
+        next_id: 0,
+        live: BTreeMap::new(),
     };
     let mixer = Set(UnsafeCell::new(SetInner {
         recv: msg_recv,
@@ -44,11 +50,17 @@ pub struct SetHandle<T> {
     old_senders: VecDeque<spsc::Sender<Msg<T>>>,
     signal_capacity: usize,
     active_signals: usize,
+    next_id: u64,
+    /// IDs and priorities of signals that have not yet been freed
+    live: BTreeMap<u64, i32>,
 }
 
 impl<T> SetHandle<T> {
     /// Add `signal` to the set
-    pub fn insert(&mut self, signal: T) {
+    ///
+    /// Signals with higher `priority` precede those with lower priority in the [`Set`]. Signals
+    /// with equal priority are ordered by insertion.
+    pub fn insert(&mut self, signal: T, priority: i32) -> SignalId {
         self.gc();
         if self.active_signals == self.signal_capacity {
             self.signal_capacity *= 2;
@@ -57,8 +69,49 @@ impl<T> SetHandle<T> {
             self.send(Msg::ReallocSignals(signals, free_send));
             self.next_free.push_back(free_recv);
         }
-        self.send(Msg::Insert(signal));
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(Msg::Insert(Entry { id, priority }, signal));
         self.active_signals += 1;
+        self.live.insert(id, priority);
+        SignalId(id)
+    }
+
+    /// Remove the signal identified by `id` from the set, if it's still present
+    pub fn remove(&mut self, id: SignalId) {
+        self.gc();
+        if let Some(&priority) = self.live.get(&id.0) {
+            self.send(Msg::Remove(Entry { id: id.0, priority }));
+        }
+    }
+
+    /// Change the priority of the signal identified by `id`, if it's still present
+    pub fn set_priority(&mut self, id: SignalId, priority: i32) {
+        self.gc();
+        if let Some(current) = self.live.get_mut(&id.0) {
+            let entry = Entry {
+                id: id.0,
+                priority: mem::replace(current, priority),
+            };
+            self.send(Msg::SetPriority(entry, priority));
+        }
+    }
+
+    /// Whether the signal identified by `id` may still be in the set
+    ///
+    /// Signals are only known to have left the set once the [`Set`] frees them.
+    pub fn contains(&mut self, id: SignalId) -> bool {
+        self.gc();
+        self.live.contains_key(&id.0)
+    }
+
+    /// Apply `command` to every signal in the set
+    ///
+    /// Takes effect on the same [`Set::update`] call for every signal, after any signals inserted
+    /// previously have been added.
+    pub fn command(&mut self, command: Command) {
+        self.gc();
+        self.send(Msg::Command(command));
     }
 
     /// Send a message, allocating more storage to do so if necessary
@@ -78,11 +131,10 @@ impl<T> SetHandle<T> {
 
     // Free old signals
     fn gc(&mut self) {
-        while self
-            .old_senders
-            .front_mut()
-            .map_or(false, |x| x.is_closed())
-        {
+        while let Some(x) = self.old_senders.front_mut() {
+            if !x.is_closed() {
+                break;
+            }
             self.old_senders.pop_front();
         }
         loop {
@@ -107,8 +159,9 @@ impl<T> SetHandle<T> {
         self.free.update();
         for x in self.free.drain() {
             match x {
-                Free::Signal(_) => {
+                Free::Signal(id, _) => {
                     self.active_signals -= 1;
+                    self.live.remove(&id);
                 }
                 Free::Table(x) => {
                     debug_assert_eq!(x.len(), 0, "signals were transferred to new table");
@@ -133,7 +186,7 @@ struct SetInner<T> {
     signals: SignalTable<T>,
 }
 
-impl<T> SetInner<T> {
+impl<T: Voice> SetInner<T> {
     fn drain_msgs(&mut self) {
         self.recv.update();
         while let Some(msg) = self.recv.pop() {
@@ -152,35 +205,58 @@ impl<T> SetInner<T> {
                         .send(Free::Table(old), 0)
                         .unwrap_or_else(|_| unreachable!("fresh channel must have capacity"));
                 }
-                Insert(signal) => {
+                Insert(entry, signal) => {
                     assert!(
                         self.signals.len() < self.signals.capacity(),
                         "mixer never does its own realloc"
                     );
-                    self.signals.push(signal);
+                    self.signals.insert(entry, signal);
+                }
+                Remove(entry) => {
+                    if let Some(index) = self.signals.position(entry) {
+                        self.remove(index);
+                    }
+                }
+                SetPriority(entry, priority) => {
+                    if let Some(index) = self.signals.position(entry) {
+                        self.signals.set_priority(index, priority);
+                    }
+                }
+                Command(command) => {
+                    for signal in self.signals.signals.iter() {
+                        signal.apply(&command);
+                    }
                 }
             }
         }
     }
 }
 
+impl<T> SetInner<T> {
+    fn remove(&mut self, index: usize) {
+        let (entry, signal) = self.signals.remove(index);
+        self.free
+            .send(Free::Signal(entry.id, signal), 0)
+            .unwrap_or_else(|_| unreachable!("free queue has capacity for every signal"));
+    }
+}
+
 unsafe impl<T> Send for Set<T> {}
 
 impl<T> Set<T> {
     /// Process changes to the set
-    pub fn update(&mut self) {
+    pub fn update(&mut self)
+    where
+        T: Voice,
+    {
         let this = unsafe { &mut (*self.0.get()) };
         this.drain_msgs();
     }
 
-    /// Remove `index` from the set
-    ///
-    /// The last element in the set replaces it, as in `Vec::swap_remove`.
+    /// Remove `index` from the set, shifting later elements down
     pub fn remove(&mut self, index: usize) {
         let this = unsafe { &mut (*self.0.get()) };
-        this.free
-            .send(Free::Signal(this.signals.swap_remove(index)), 0)
-            .unwrap_or_else(|_| unreachable!("free queue has capacity for every signal"));
+        this.remove(index);
     }
 }
 
@@ -188,27 +264,118 @@ impl<T> Deref for Set<T> {
     type Target = [T];
     fn deref(&self) -> &[T] {
         let this = unsafe { &mut (*self.0.get()) };
-        &this.signals
+        &this.signals.signals
+    }
+}
+
+/// Identifies a signal inserted into a [`Mixer`](crate::Mixer)
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SignalId(u64);
+
+#[derive(Debug, Copy, Clone)]
+struct Entry {
+    id: u64,
+    priority: i32,
+}
+
+impl Entry {
+    /// Key by which entries are ordered
+    fn key(&self) -> (Reverse<i32>, u64) {
+        (Reverse(self.priority), self.id)
+    }
+}
+
+/// Signals in descending order of priority, then ascending order of insertion
+///
+/// Order is maintained by each change, so elements are located by binary search and never need
+/// sorting. Shifting elements to make or close a gap is a single `memmove`.
+struct SignalTable<T> {
+    signals: Vec<T>,
+    /// Identity and priority of each element of `signals`
+    entries: Vec<Entry>,
+}
+
+impl<T> SignalTable<T> {
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            signals: Vec::with_capacity(capacity),
+            entries: Vec::with_capacity(capacity),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.signals.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.signals.capacity()
+    }
+
+    fn insert(&mut self, entry: Entry, signal: T) {
+        let index = self.search(entry).unwrap_or_else(|x| x);
+        self.entries.insert(index, entry);
+        self.signals.insert(index, signal);
+    }
+
+    fn remove(&mut self, index: usize) -> (Entry, T) {
+        (self.entries.remove(index), self.signals.remove(index))
+    }
+
+    fn set_priority(&mut self, index: usize, priority: i32) {
+        let (mut entry, signal) = self.remove(index);
+        entry.priority = priority;
+        self.insert(entry, signal);
+    }
+
+    /// Index of the element identified by `entry`, which must have that element's priority
+    fn position(&self, entry: Entry) -> Option<usize> {
+        self.search(entry).ok()
+    }
+
+    fn search(&self, entry: Entry) -> Result<usize, usize> {
+        self.entries.binary_search_by_key(&entry.key(), Entry::key)
+    }
+
+    /// Move all elements of `other` into `self`, which must be empty
+    fn append(&mut self, other: &mut Self) {
+        debug_assert_eq!(self.len(), 0);
+        self.entries.append(&mut other.entries);
+        self.signals.append(&mut other.signals);
     }
 }
 
-type SignalTable<T> = Vec<T>;
+/// Elements of a [`Set`] that can be controlled by a [`Command`]
+pub trait Voice {
+    /// Carry out `command` if it applies to this element
+    fn apply(&self, command: &Command);
+}
+
+impl<T: Voice + ?Sized> Voice for Arc<T> {
+    fn apply(&self, command: &Command) {
+        (**self).apply(command);
+    }
+}
 
 enum Msg<T> {
     ReallocChannel(spsc::Receiver<Msg<T>>),
     ReallocSignals(SignalTable<T>, spsc::Sender<Free<T>>),
-    Insert(T),
+    Insert(Entry, T),
+    /// Remove the signal with this identity and current priority
+    Remove(Entry),
+    /// Change the priority of the signal with this identity and current priority
+    SetPriority(Entry, i32),
+    Command(Command),
 }
 
 enum Free<T> {
-    Table(Vec<T>),
-    Signal(T),
+    Table(SignalTable<T>),
+    Signal(u64, T),
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{Frames, FramesSignal};
+    use crate::{Constant, Frames, FramesSignal, Signal, Stop};
 
     const RATE: u32 = 10;
 
@@ -217,7 +384,7 @@ mod tests {
         let (mut remote, mut s) = set();
         let signal = FramesSignal::from(Frames::from_slice(RATE, &[[0.0; 2]; RATE as usize]));
         for i in 1..=(INITIAL_SIGNALS_CAPACITY + 2) {
-            remote.insert(signal.clone());
+            remote.insert(Stop::new(signal.clone(), None), 0);
             s.update();
             assert_eq!(unsafe { (*s.0.get()).signals.len() }, i);
         }
@@ -228,7 +395,7 @@ mod tests {
         let (mut remote, mut s) = set();
         let signal = FramesSignal::from(Frames::from_slice(RATE, &[[0.0; 2]; RATE as usize]));
         for _ in 0..(INITIAL_CHANNEL_CAPACITY + 2) {
-            remote.insert(signal.clone());
+            remote.insert(Stop::new(signal.clone(), None), 0);
         }
         assert_eq!(remote.sender.capacity(), 1 + 2 * INITIAL_CHANNEL_CAPACITY);
         assert_eq!(unsafe { (*s.0.get()).signals.len() }, 0);
@@ -238,4 +405,42 @@ mod tests {
             INITIAL_CHANNEL_CAPACITY + 2
         );
     }
+
+    #[test]
+    fn priority() {
+        let (mut remote, mut s) = set();
+        let a = remote.insert(Stop::new(Constant::new(1.0), None), 0);
+        let b = remote.insert(Stop::new(Constant::new(2.0), None), 1);
+        let c = remote.insert(Stop::new(Constant::new(3.0), None), 0);
+        let values = |s: &Set<Stop<Constant<f32>>>| {
+            s.iter()
+                .map(|x| {
+                    let mut buf = [0.0];
+                    x.sample(1.0, &mut buf);
+                    buf[0]
+                })
+                .collect::<Vec<_>>()
+        };
+        s.update();
+        assert_eq!(values(&s), [2.0, 1.0, 3.0]);
+
+        remote.set_priority(c, 2);
+        remote.remove(a);
+        s.update();
+        assert_eq!(values(&s), [3.0, 2.0]);
+
+        // Removal by index preserves order
+        let d = remote.insert(Stop::new(Constant::new(4.0), None), 1);
+        remote.insert(Stop::new(Constant::new(5.0), None), 1);
+        s.update();
+        assert_eq!(values(&s), [3.0, 2.0, 4.0, 5.0]);
+        s.remove(0);
+        assert_eq!(values(&s), [2.0, 4.0, 5.0]);
+        remote.set_priority(d, 0);
+        s.update();
+        assert_eq!(values(&s), [2.0, 5.0, 4.0]);
+
+        assert!(!remote.contains(a));
+        assert!(remote.contains(b));
+    }
 }
@@ -113,7 +113,7 @@ mod tests {
     fn mono_to_stereo() {
         let signal = MonoToStereo::new(CountingSignal(Cell::new(0)));
         let mut buf = [[0.0; 2]; 4];
-        signal.sample(1.0, (&mut buf[..]).into());
+        signal.sample(1.0, &mut buf);
         assert_eq!(buf, [[0.0, 0.0], [1.0, 1.0], [2.0, 2.0], [3.0, 3.0]]);
     }
 }
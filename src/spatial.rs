@@ -4,12 +4,14 @@ use core::{
     ops::{Index, IndexMut},
 };
 
+#[cfg(not(feature = "no_std"))]
+use crate::workers::{AssertSync, Parallel};
 use crate::{
     math::{add, dot, invert_quat, mix, norm, rotate, scale, sub, Float},
     ring::Ring,
-    set::{set, Set, SetHandle},
+    set::{set, Set, SetHandle, Voice},
     swap::Swap,
-    Controlled, Filter, FilterHaving, Handle, Sample, Seek, Signal, Stop,
+    Action, Command, Controlled, Filter, FilterHaving, Handle, Sample, Seek, Signal, Stop, Voices,
 };
 
 type ErasedSpatialBuffered = Arc<SpatialBuffered<Stop<dyn Signal<Frame = Sample> + Send>>>;
@@ -59,6 +61,12 @@ impl<T: ?Sized> Filter for SpatialBuffered<T> {
     }
 }
 
+impl<T: Voice + ?Sized> Voice for SpatialBuffered<T> {
+    fn apply(&self, command: &Command) {
+        self.inner.apply(command);
+    }
+}
+
 unsafe impl<'a, T: 'a> Controlled<'a> for SpatialBuffered<T> {
     type Control = SpatialControl<'a>;
 
@@ -94,6 +102,12 @@ impl<T: ?Sized> Filter for Spatial<T> {
     }
 }
 
+impl<T: Voice + ?Sized> Voice for Spatial<T> {
+    fn apply(&self, command: &Command) {
+        self.inner.apply(command);
+    }
+}
+
 unsafe impl<'a, T: 'a> Controlled<'a> for Spatial<T> {
     type Control = SpatialControl<'a>;
 
@@ -161,6 +175,8 @@ pub struct SpatialScene {
     rot: Swap<mint::Quaternion<f32>>,
     recv_buffered: RefCell<Set<ErasedSpatialBuffered>>,
     recv: RefCell<Set<ErasedSpatial>>,
+    #[cfg(not(feature = "no_std"))]
+    parallel: RefCell<Option<Parallel<[Sample; 2]>>>,
 }
 
 impl SpatialScene {
@@ -180,8 +196,23 @@ impl SpatialScene {
             rot,
             recv_buffered: RefCell::new(buffered_set),
             recv: RefCell::new(seek_set),
+            #[cfg(not(feature = "no_std"))]
+            parallel: RefCell::new(None),
         }
     }
+
+    /// Like [`new`](Self::new), but spatializes signals in parallel using `workers` additional
+    /// threads
+    ///
+    /// Blocks are divided into chunks of at most 1024 frames, each of which is processed as if it
+    /// were sampled separately. See [`Mixer::with_workers`](crate::Mixer::with_workers) for
+    /// details.
+    #[cfg(not(feature = "no_std"))]
+    pub fn with_workers(workers: usize) -> Self {
+        let scene = Self::new();
+        *scene.parallel.borrow_mut() = Some(Parallel::new(workers, 1024));
+        scene
+    }
 }
 
 unsafe impl Send for SpatialScene {}
@@ -192,16 +223,17 @@ impl Default for SpatialScene {
     }
 }
 
-fn walk_set<T, U, I>(
+/// Retire finished signals and compute the listener-relative motion of the rest over the next
+/// `elapsed` seconds
+fn update_set<T, U, I>(
     set: &mut Set<Arc<T>>,
     get_common: impl Fn(&T) -> &Common,
     prev_rot: &mint::Quaternion<f32>,
     rot: &mint::Quaternion<f32>,
     elapsed: f32,
-    mut mix_signal: impl FnMut(&T, mint::Point3<f32>, mint::Point3<f32>),
 ) where
-    T: FilterHaving<Stop<U>, I> + ?Sized,
-    U: Signal + ?Sized,
+    T: FilterHaving<Stop<U>, I> + Voice + ?Sized,
+    U: Signal<Frame = Sample> + ?Sized,
 {
     set.update();
     for i in (0..set.len()).rev() {
@@ -212,12 +244,12 @@ fn walk_set<T, U, I>(
             stop.handle_dropped();
         }
 
+        let mut state = common.state.borrow_mut();
         let prev_position;
         let next_position;
         unsafe {
             // Compute the signal's smoothed start/end positions over the sampled period
             // TODO: Use historical positions
-            let mut state = common.state.borrow_mut();
 
             // Update motion
             let orig_next = *common.motion.received();
@@ -253,16 +285,89 @@ fn walk_set<T, U, I>(
             stop.stop();
         }
         if stop.is_stopped() {
+            drop(state);
             set.remove(i);
             continue;
         }
 
-        if stop.is_paused() {
-            continue;
+        state.block = if stop.is_paused() {
+            None
+        } else {
+            Some((prev_position, next_position))
+        };
+    }
+}
+
+/// Mix a buffered signal into `out`, which spans `elapsed` seconds
+fn mix_buffered<T>(signal: &SpatialBuffered<T>, elapsed: f32, out: &mut [[Sample; 2]])
+where
+    T: Signal<Frame = Sample> + ?Sized,
+{
+    let (prev_position, next_position) = match signal.common.state.borrow().block {
+        Some(x) => x,
+        None => return,
+    };
+    debug_assert!(signal.max_delay >= elapsed);
+
+    // Extend delay queue with new data
+    signal
+        .queue
+        .borrow_mut()
+        .write(&signal.inner, signal.rate, elapsed);
+
+    // Mix into output
+    for &ear in &[Ear::Left, Ear::Right] {
+        let prev_state = EarState::new(prev_position, ear, signal.common.radius);
+        let next_state = EarState::new(next_position, ear, signal.common.radius);
+
+        // Clamp into the max length of the delay queue
+        let prev_offset = (prev_state.offset - elapsed).max(-signal.max_delay);
+        let next_offset = next_state.offset.max(-signal.max_delay);
+
+        let dt = (next_offset - prev_offset) / out.len() as f32;
+        let d_gain = (next_state.gain - prev_state.gain) / out.len() as f32;
+
+        for (i, frame) in out.iter_mut().enumerate() {
+            let gain = prev_state.gain + i as f32 * d_gain;
+            let t = prev_offset + i as f32 * dt;
+            frame[ear as usize] += signal.queue.borrow().sample(signal.rate, t) * gain;
         }
+    }
+}
 
-        mix_signal(signal, prev_position, next_position);
+/// Mix a seekable signal into `out`, which spans `elapsed` seconds
+fn mix_seekable<T>(signal: &Spatial<T>, elapsed: f32, out: &mut [[Sample; 2]])
+where
+    T: Seek<Frame = Sample> + ?Sized,
+{
+    let (prev_position, next_position) = match signal.common.state.borrow().block {
+        Some(x) => x,
+        None => return,
+    };
+    for &ear in &[Ear::Left, Ear::Right] {
+        let prev_state = EarState::new(prev_position, ear, signal.common.radius);
+        let next_state = EarState::new(next_position, ear, signal.common.radius);
+        signal.inner.seek(prev_state.offset); // Initial real time -> Initial delayed
+
+        let effective_elapsed = (elapsed + next_state.offset) - prev_state.offset;
+        let dt = effective_elapsed / out.len() as f32;
+        let d_gain = (next_state.gain - prev_state.gain) / out.len() as f32;
+
+        let mut buf = [0.0; 256];
+        let mut i = 0;
+        for chunk in out.chunks_mut(buf.len()) {
+            signal.inner.sample(dt, &mut buf[..chunk.len()]);
+            for (s, o) in buf.iter().copied().zip(chunk) {
+                let gain = prev_state.gain + i as f32 * d_gain;
+                o[ear as usize] += s * gain;
+                i += 1;
+            }
+        }
+        // Final delayed -> Initial real time
+        signal.inner.seek(-effective_elapsed - prev_state.offset);
     }
+    // Initial real time -> Final real time
+    signal.inner.seek(elapsed);
 }
 
 /// Control for modifying a [`SpatialScene`]
@@ -292,17 +397,43 @@ impl<'a> SpatialSceneControl<'a> {
     /// The type of signal given determines what additional controls can be used. See the
     /// examples for a detailed guide.
     pub fn play<S>(&mut self, signal: S, options: SpatialOptions) -> Handle<Spatial<Stop<S>>>
+    where
+        S: Seek<Frame = Sample> + Send + 'static,
+    {
+        self.play_inner(signal, options, None)
+    }
+
+    /// Like [`play`](Self::play), but the signal can also be controlled together with every other
+    /// signal sharing the same `tag` by passing [`Voices::Tagged`] to e.g. [`pause`](Self::pause)
+    pub fn play_tagged<S>(
+        &mut self,
+        signal: S,
+        options: SpatialOptions,
+        tag: u32,
+    ) -> Handle<Spatial<Stop<S>>>
+    where
+        S: Seek<Frame = Sample> + Send + 'static,
+    {
+        self.play_inner(signal, options, Some(tag))
+    }
+
+    fn play_inner<S>(
+        &mut self,
+        signal: S,
+        options: SpatialOptions,
+        tag: Option<u32>,
+    ) -> Handle<Spatial<Stop<S>>>
     where
         S: Seek<Frame = Sample> + Send + 'static,
     {
         let signal = Arc::new(Spatial::new(
-            Stop::new(signal),
+            Stop::new(signal, tag),
             options.position,
             options.velocity,
             options.radius,
         ));
         let handle = unsafe { Handle::from_arc(signal.clone()) };
-        self.0.send.borrow_mut().insert(signal);
+        self.0.send.borrow_mut().insert(signal, 0);
         handle
     }
 
@@ -324,19 +455,58 @@ impl<'a> SpatialSceneControl<'a> {
         rate: u32,
         buffer_duration: f32,
     ) -> Handle<SpatialBuffered<Stop<S>>>
+    where
+        S: Signal<Frame = Sample> + Send + 'static,
+    {
+        self.play_buffered_inner(signal, options, max_distance, rate, buffer_duration, None)
+    }
+
+    /// Like [`play_buffered`](Self::play_buffered), but tagged like
+    /// [`play_tagged`](Self::play_tagged)
+    pub fn play_buffered_tagged<S>(
+        &mut self,
+        signal: S,
+        options: SpatialOptions,
+        max_distance: f32,
+        rate: u32,
+        buffer_duration: f32,
+        tag: u32,
+    ) -> Handle<SpatialBuffered<Stop<S>>>
+    where
+        S: Signal<Frame = Sample> + Send + 'static,
+    {
+        self.play_buffered_inner(
+            signal,
+            options,
+            max_distance,
+            rate,
+            buffer_duration,
+            Some(tag),
+        )
+    }
+
+    fn play_buffered_inner<S>(
+        &mut self,
+        signal: S,
+        options: SpatialOptions,
+        max_distance: f32,
+        rate: u32,
+        buffer_duration: f32,
+        tag: Option<u32>,
+    ) -> Handle<SpatialBuffered<Stop<S>>>
     where
         S: Signal<Frame = Sample> + Send + 'static,
     {
         let signal = Arc::new(SpatialBuffered::new(
             rate,
-            Stop::new(signal),
+            Stop::new(signal, tag),
             options.position,
             options.velocity,
             max_distance / SPEED_OF_SOUND + buffer_duration,
             options.radius,
         ));
         let handle = unsafe { Handle::from_arc(signal.clone()) };
-        self.0.send_buffered.borrow_mut().insert(signal);
+        self.0.send_buffered.borrow_mut().insert(signal, 0);
         handle
     }
 
@@ -350,6 +520,35 @@ impl<'a> SpatialSceneControl<'a> {
         }
         self.0.rot.flush();
     }
+
+    /// Suspend playback of the selected signals
+    ///
+    /// Like the other commands below, this applies to every selected signal on the same block,
+    /// including signals whose handles have been dropped.
+    pub fn pause(&mut self, voices: Voices) {
+        self.command(voices, Action::Pause);
+    }
+
+    /// Resume playback of the selected signals, if paused
+    pub fn resume(&mut self, voices: Voices) {
+        self.command(voices, Action::Resume);
+    }
+
+    /// Stop the selected signals for good
+    pub fn stop(&mut self, voices: Voices) {
+        self.command(voices, Action::Stop);
+    }
+
+    /// Linearly fade the selected signals to silence over `seconds`, then stop them
+    pub fn fade_out(&mut self, voices: Voices, seconds: f32) {
+        self.command(voices, Action::FadeOut(seconds));
+    }
+
+    fn command(&mut self, voices: Voices, action: Action) {
+        let command = Command { voices, action };
+        self.0.send_buffered.borrow_mut().command(command);
+        self.0.send.borrow_mut().command(command);
+    }
 }
 
 /// Passed to [`SpatialSceneControl::play`]
@@ -373,14 +572,9 @@ impl Default for SpatialOptions {
     }
 }
 
-impl Signal for SpatialScene {
-    type Frame = [Sample; 2];
-
-    fn sample(&self, interval: f32, out: &mut [[Sample; 2]]) {
-        let set = &mut *self.recv_buffered.borrow_mut();
-        // Update set contents
-        set.update();
-
+impl SpatialScene {
+    /// Prepare every signal to be mixed over the next `elapsed` seconds
+    fn update(&self, elapsed: f32) {
         // Update listener rotation
         let (prev_rot, rot) = unsafe {
             let prev = *self.rot.received();
@@ -388,84 +582,67 @@ impl Signal for SpatialScene {
             (prev, *self.rot.received())
         };
 
-        // Zero output in preparation for mixing
-        for frame in &mut *out {
-            *frame = [0.0; 2];
-        }
-
-        let elapsed = interval * out.len() as f32;
-        walk_set(
-            set,
+        update_set(
+            &mut self.recv_buffered.borrow_mut(),
             |signal| &signal.common,
             &prev_rot,
             &rot,
             elapsed,
-            |signal, prev_position, next_position| {
-                debug_assert!(signal.max_delay >= elapsed);
-
-                // Extend delay queue with new data
-                signal
-                    .queue
-                    .borrow_mut()
-                    .write(&signal.inner, signal.rate, elapsed);
-
-                // Mix into output
-                for &ear in &[Ear::Left, Ear::Right] {
-                    let prev_state = EarState::new(prev_position, ear, signal.common.radius);
-                    let next_state = EarState::new(next_position, ear, signal.common.radius);
-
-                    // Clamp into the max length of the delay queue
-                    let prev_offset = (prev_state.offset - elapsed).max(-signal.max_delay);
-                    let next_offset = next_state.offset.max(-signal.max_delay);
-
-                    let dt = (next_offset - prev_offset) / out.len() as f32;
-                    let d_gain = (next_state.gain - prev_state.gain) / out.len() as f32;
-
-                    for (i, frame) in out.iter_mut().enumerate() {
-                        let gain = prev_state.gain + i as f32 * d_gain;
-                        let t = prev_offset + i as f32 * dt;
-                        frame[ear as usize] += signal.queue.borrow().sample(signal.rate, t) * gain;
-                    }
-                }
-            },
         );
-
-        let set = &mut *self.recv.borrow_mut();
-        // Update set contents
-        set.update();
-        walk_set(
-            set,
+        update_set(
+            &mut self.recv.borrow_mut(),
             |signal| &signal.common,
             &prev_rot,
             &rot,
             elapsed,
-            |signal, prev_position, next_position| {
-                for &ear in &[Ear::Left, Ear::Right] {
-                    let prev_state = EarState::new(prev_position, ear, signal.common.radius);
-                    let next_state = EarState::new(next_position, ear, signal.common.radius);
-                    signal.inner.seek(prev_state.offset); // Initial real time -> Initial delayed
-
-                    let effective_elapsed = (elapsed + next_state.offset) - prev_state.offset;
-                    let dt = effective_elapsed / out.len() as f32;
-                    let d_gain = (next_state.gain - prev_state.gain) / out.len() as f32;
-
-                    let mut buf = [0.0; 256];
-                    let mut i = 0;
-                    for chunk in out.chunks_mut(buf.len()) {
-                        signal.inner.sample(dt, &mut buf[..chunk.len()]);
-                        for (s, o) in buf.iter().copied().zip(chunk) {
-                            let gain = prev_state.gain + i as f32 * d_gain;
-                            o[ear as usize] += s * gain;
-                            i += 1;
+        );
+    }
+}
+
+impl Signal for SpatialScene {
+    type Frame = [Sample; 2];
+
+    fn sample(&self, interval: f32, out: &mut [[Sample; 2]]) {
+        #[cfg(not(feature = "no_std"))]
+        if let Some(ref mut parallel) = *self.parallel.borrow_mut() {
+            for chunk in out.chunks_mut(parallel.capacity()) {
+                let elapsed = interval * chunk.len() as f32;
+                self.update(elapsed);
+                let buffered = self.recv_buffered.borrow();
+                let seekable = self.recv.borrow();
+                let buffered = AssertSync(&*buffered);
+                let seekable = AssertSync(&*seekable);
+                parallel.mix(chunk, |participant, participants, out| {
+                    for (i, signal) in buffered.0.iter().enumerate() {
+                        if i % participants == participant {
+                            mix_buffered(signal, elapsed, out);
                         }
                     }
-                    // Final delayed -> Initial real time
-                    signal.inner.seek(-effective_elapsed - prev_state.offset);
-                }
-                // Initial real time -> Final real time
-                signal.inner.seek(elapsed);
-            },
-        );
+                    let offset = buffered.0.len();
+                    for (i, signal) in seekable.0.iter().enumerate() {
+                        if (offset + i) % participants == participant {
+                            mix_seekable(signal, elapsed, out);
+                        }
+                    }
+                });
+            }
+            return;
+        }
+
+        let elapsed = interval * out.len() as f32;
+        self.update(elapsed);
+
+        // Zero output in preparation for mixing
+        for frame in &mut *out {
+            *frame = [0.0; 2];
+        }
+
+        for signal in self.recv_buffered.borrow().iter() {
+            mix_buffered(signal, elapsed, out);
+        }
+        for signal in self.recv.borrow().iter() {
+            mix_seekable(signal, elapsed, out);
+        }
     }
 
     #[inline]
@@ -486,6 +663,9 @@ struct State {
     prev_position: mint::Point3<f32>,
     /// Seconds since position/vel were updated
     dt: f32,
+    /// Listener-relative positions at the start and end of the block being sampled, if the signal
+    /// should be mixed into it
+    block: Option<(mint::Point3<f32>, mint::Point3<f32>)>,
 }
 
 impl State {
@@ -493,6 +673,7 @@ impl State {
         Self {
             prev_position: position,
             dt: 0.0,
+            block: None,
         }
     }
 
@@ -1,12 +1,20 @@
-use core::sync::atomic::{AtomicU32, Ordering};
+use core::{
+    cell::{Cell, RefCell},
+    sync::atomic::{AtomicU32, Ordering},
+};
 
-use crate::{Controlled, Filter, Frame, Signal};
+use crate::{automation::Automated, swap::Swap, Automation, Controlled, Filter, Frame, Signal};
 
 /// Scales rate of playback by a dynamically-adjustable factor
 ///
 /// Higher/lower speeds will naturally result in higher/lower pitched sound respectively.
 pub struct Speed<T: ?Sized> {
     speed: AtomicU32,
+    /// Automation of the speed, or `None` to follow `speed`
+    automation: Swap<Option<Automation>>,
+    automated: RefCell<Option<Automated>>,
+    /// Speed as of the end of the last call to `sample`
+    current: Cell<f32>,
     inner: T,
 }
 
@@ -15,6 +23,9 @@ impl<T> Speed<T> {
     pub fn new(signal: T) -> Self {
         Self {
             speed: AtomicU32::new(1.0f32.to_bits()),
+            automation: Swap::new(None),
+            automated: RefCell::new(None),
+            current: Cell::new(1.0),
             inner: signal,
         }
     }
@@ -27,13 +38,32 @@ where
     type Frame = T::Frame;
 
     fn sample(&self, interval: f32, out: &mut [T::Frame]) {
-        let speed = f32::from_bits(self.speed.load(Ordering::Relaxed));
-        self.inner.sample(interval * speed, out);
+        let mut automated = self.automated.borrow_mut();
+        if self.automation.refresh() {
+            *automated = unsafe { *self.automation.received() }
+                .map(|x| Automated::new(x, self.current.get()));
+        }
+        let automated = match *automated {
+            Some(ref mut x) => x,
+            None => {
+                let speed = f32::from_bits(self.speed.load(Ordering::Relaxed));
+                self.current.set(speed);
+                self.inner.sample(interval * speed, out);
+                return;
+            }
+        };
+        // Each call to the inner signal takes a single interval, so sample a frame at a time
+        for frame in out.chunks_mut(1) {
+            // Use the speed at the middle of the frame
+            automated.advance(interval / 2.0);
+            self.inner.sample(interval * automated.get(), frame);
+            automated.advance(interval / 2.0);
+        }
+        self.current.set(automated.get());
     }
 
     fn remaining(&self) -> f32 {
-        let speed = f32::from_bits(self.speed.load(Ordering::Relaxed));
-        self.inner.remaining() / speed
+        self.inner.remaining() / self.current.get()
     }
 
     #[inline]
@@ -50,24 +80,85 @@ impl<T> Filter for Speed<T> {
 }
 
 /// Thread-safe control for a [`Speed`] filter
-pub struct SpeedControl<'a>(&'a AtomicU32);
+pub struct SpeedControl<'a> {
+    speed: &'a AtomicU32,
+    automation: &'a Swap<Option<Automation>>,
+}
 
 unsafe impl<'a, T: 'a> Controlled<'a> for Speed<T> {
     type Control = SpeedControl<'a>;
 
     unsafe fn make_control(signal: &'a Speed<T>) -> Self::Control {
-        SpeedControl(&signal.speed)
+        SpeedControl {
+            speed: &signal.speed,
+            automation: &signal.automation,
+        }
     }
 }
 
 impl<'a> SpeedControl<'a> {
-    /// Get the current speed
+    /// Get the speed most recently set
+    ///
+    /// Doesn't reflect automation.
     pub fn speed(&self) -> f32 {
-        f32::from_bits(self.0.load(Ordering::Relaxed))
+        f32::from_bits(self.speed.load(Ordering::Relaxed))
     }
 
-    /// Adjust the speed
+    /// Adjust the speed, cancelling automation in progress
     pub fn set_speed(&mut self, factor: f32) {
-        self.0.store(factor.to_bits(), Ordering::Relaxed);
+        self.speed.store(factor.to_bits(), Ordering::Relaxed);
+        self.send(None);
+    }
+
+    /// Drive the speed with `automation`, replacing any automation in progress
+    ///
+    /// The speed is updated every frame, and holds the final value of the automation until it's
+    /// changed again. See [`Automation`] for details.
+    pub fn automate(&mut self, automation: Automation) {
+        self.send(Some(automation));
+    }
+
+    fn send(&mut self, automation: Option<Automation>) {
+        unsafe {
+            *self.automation.pending() = automation;
+        }
+        self.automation.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Curve;
+
+    /// Emits the interval it's sampled at
+    struct Interval;
+
+    impl Signal for Interval {
+        type Frame = f32;
+
+        fn sample(&self, interval: f32, out: &mut [f32]) {
+            out.fill(interval);
+        }
+    }
+
+    #[test]
+    fn automation() {
+        let s = Speed::new(Interval);
+        let mut control = unsafe { Speed::make_control(&s) };
+        control.automate(Automation::new().then(1.0, 3.0, Curve::Linear));
+        let mut buf = [0.0; 6];
+        s.sample(0.25, &mut buf);
+        // Each frame plays at the speed at its midpoint
+        let expected = [1.25, 1.75, 2.25, 2.75, 3.0, 3.0].map(|x| x * 0.25);
+        for (x, y) in buf.iter().zip(&expected) {
+            assert!((x - y).abs() < 1e-6, "{} vs {}", x, y);
+        }
+        assert_eq!(s.current.get(), 3.0);
+
+        // Setting the speed cancels automation
+        control.set_speed(0.5);
+        s.sample(0.25, &mut buf);
+        assert_eq!(buf, [0.125; 6]);
     }
 }
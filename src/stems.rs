@@ -0,0 +1,221 @@
+use alloc::{boxed::Box, sync::Arc};
+use core::{
+    cell::{Cell, RefCell},
+    sync::atomic::{AtomicIsize, AtomicU32, Ordering},
+};
+
+use crate::{frame, math::Float, Controlled, Frame, Frames, Seek, Signal, Smoothed};
+
+/// A [`Signal`] that plays several [`Frames`] in lockstep, each with its own gain
+///
+/// Useful for vertical remixing, where a song is divided into stems such as drums, bass, and
+/// melody, and each is faded in or out to follow the intensity of gameplay. Every stem is read
+/// from a single shared playback position, so unlike separate
+/// [`FramesSignal`](crate::FramesSignal)s in a [`Mixer`](crate::Mixer), they can never drift apart.
+///
+/// Stems may differ in length, in which case shorter stems fall silent once they end.
+pub struct StemPlayer<T> {
+    stems: Box<[Arc<Frames<T>>]>,
+    /// Target amplitude ratio of each stem
+    shared: Box<[AtomicU32]>,
+    gains: RefCell<Box<[Smoothed<f32>]>>,
+    looping: bool,
+    /// Seconds into the longest stem
+    t: Cell<f64>,
+    /// Approximation of t in samples of the first stem, for reading from the control
+    sample_t: AtomicIsize,
+}
+
+impl<T> StemPlayer<T> {
+    /// Play `stems` together from the beginning, each initially at full volume
+    ///
+    /// If `looping` is set, playback restarts from the beginning at the end of the longest stem.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `stems` is empty.
+    pub fn new(stems: &[Arc<Frames<T>>], looping: bool) -> Self {
+        assert!(!stems.is_empty(), "at least one stem is required");
+        Self {
+            stems: stems.into(),
+            shared: stems
+                .iter()
+                .map(|_| AtomicU32::new(1.0f32.to_bits()))
+                .collect(),
+            gains: RefCell::new(stems.iter().map(|_| Smoothed::new(1.0)).collect()),
+            looping,
+            t: Cell::new(0.0),
+            sample_t: AtomicIsize::new(0),
+        }
+    }
+
+    /// Duration of the longest stem, in seconds
+    fn runtime(&self) -> f64 {
+        self.stems.iter().map(|x| x.runtime()).fold(0.0, f64::max)
+    }
+
+    fn set_t(&self, t: f64) {
+        let runtime = self.runtime();
+        let t = if self.looping && runtime > 0.0 {
+            t.rem_euclid(runtime)
+        } else {
+            t
+        };
+        self.t.set(t);
+        self.sample_t.store(
+            (t * f64::from(self.stems[0].rate())) as isize,
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Frame of `stem` at `t` seconds, given that the longest stem lasts `runtime` seconds
+    ///
+    /// When looping, frames past the end of the loop are read from its start, so that
+    /// interpolation across the loop point is seamless.
+    fn interpolate(&self, stem: &Frames<T>, t: f64, runtime: f64) -> T
+    where
+        T: Frame + Copy,
+    {
+        let rate = f64::from(stem.rate());
+        let s = t * rate;
+        let a = stem.interpolate(s.trunc());
+        let mut next = (s.trunc() + 1.0) / rate;
+        if self.looping && next >= runtime {
+            next -= runtime;
+        }
+        let b = stem.interpolate(next * rate);
+        frame::lerp(&a, &b, s.fract() as f32)
+    }
+}
+
+impl<T: Frame + Copy> Signal for StemPlayer<T> {
+    type Frame = T;
+
+    #[allow(clippy::float_cmp)]
+    fn sample(&self, interval: f32, out: &mut [T]) {
+        let mut gains = self.gains.borrow_mut();
+        for (gain, shared) in gains.iter_mut().zip(self.shared.iter()) {
+            let shared = f32::from_bits(shared.load(Ordering::Relaxed));
+            if gain.get() != shared {
+                gain.set(shared);
+            }
+        }
+
+        let runtime = self.runtime();
+        let mut t = self.t.get();
+        for o in out {
+            *o = T::ZERO;
+            for (stem, gain) in self.stems.iter().zip(gains.iter_mut()) {
+                let x = self.interpolate(stem, t, runtime);
+                *o = frame::mix(o, &frame::scale(&x, gain.get()));
+                gain.advance(interval / SMOOTHING_PERIOD);
+            }
+            t += f64::from(interval);
+            if self.looping && t >= runtime {
+                t -= runtime;
+            }
+        }
+        self.set_t(t);
+    }
+
+    fn remaining(&self) -> f32 {
+        if self.looping {
+            return f32::INFINITY;
+        }
+        (self.runtime() - self.t.get()) as f32
+    }
+}
+
+impl<T: Frame + Copy> Seek for StemPlayer<T> {
+    fn seek(&self, seconds: f32) {
+        self.set_t(self.t.get() + f64::from(seconds));
+    }
+}
+
+/// Thread-safe control for a [`StemPlayer`]
+pub struct StemPlayerControl<'a> {
+    shared: &'a [AtomicU32],
+    sample_t: &'a AtomicIsize,
+    rate: u32,
+}
+
+unsafe impl<'a, T: 'a> Controlled<'a> for StemPlayer<T> {
+    type Control = StemPlayerControl<'a>;
+
+    unsafe fn make_control(signal: &'a StemPlayer<T>) -> Self::Control {
+        StemPlayerControl {
+            shared: &signal.shared,
+            sample_t: &signal.sample_t,
+            rate: signal.stems[0].rate(),
+        }
+    }
+}
+
+impl<'a> StemPlayerControl<'a> {
+    /// Number of stems
+    pub fn stems(&self) -> usize {
+        self.shared.len()
+    }
+
+    /// Get the amplification of `stem` most recently set, in decibels
+    pub fn gain(&self, stem: usize) -> f32 {
+        20.0 * self.amplitude_ratio(stem).log10()
+    }
+
+    /// Amplify `stem` by `db` decibels
+    ///
+    /// Changes are smoothed to avoid popping.
+    pub fn set_gain(&mut self, stem: usize, db: f32) {
+        self.set_amplitude_ratio(stem, 10.0f32.powf(db / 20.0));
+    }
+
+    /// Get the amplitude scaling factor of `stem` most recently set
+    pub fn amplitude_ratio(&self, stem: usize) -> f32 {
+        f32::from_bits(self.shared[stem].load(Ordering::Relaxed))
+    }
+
+    /// Scale the amplitude of `stem` directly, e.g. to 0 to silence it entirely
+    pub fn set_amplitude_ratio(&mut self, stem: usize, factor: f32) {
+        self.shared[stem].store(factor.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Get the current playback position, in seconds
+    pub fn playback_position(&self) -> f64 {
+        self.sample_t.load(Ordering::Relaxed) as f64 / f64::from(self.rate)
+    }
+}
+
+/// Number of seconds over which to smooth a change in a stem's gain
+const SMOOTHING_PERIOD: f32 = 0.1;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lockstep() {
+        let a = Frames::from_slice(1, &[0.0, 1.0, 2.0, 3.0]);
+        let b = Frames::from_slice(1, &[0.0, 10.0, 20.0, 30.0]);
+        let s = StemPlayer::new(&[a, b], true);
+        let mut control = unsafe { StemPlayer::make_control(&s) };
+        let mut buf = [0.0; 6];
+        s.sample(1.0, &mut buf);
+        assert_eq!(buf, [0.0, 11.0, 22.0, 33.0, 0.0, 11.0]);
+        assert_eq!(control.playback_position(), 2.0);
+
+        // Fade out the second stem over `SMOOTHING_PERIOD`
+        control.set_amplitude_ratio(1, 0.0);
+        s.sample(0.025, &mut buf[..5]);
+        assert_eq!(buf[0], 22.0);
+        assert!((buf[4] - 2.1).abs() < 1e-4);
+    }
+
+    #[test]
+    fn loop_interpolation() {
+        let s = StemPlayer::new(&[Frames::from_slice(1, &[2.0, 1.0, 4.0])], true);
+        let mut buf = [0.0; 7];
+        s.sample(0.5, &mut buf);
+        // Between the last frame and the first
+        assert_eq!(buf, [2.0, 1.5, 1.0, 2.5, 4.0, 3.0, 2.0]);
+    }
+}
@@ -1,6 +1,9 @@
-use core::sync::atomic::{AtomicUsize, Ordering};
+use core::{
+    cell::Cell,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
-use crate::{Controlled, Filter, Seek, Signal};
+use crate::{frame, set::Voice, Controlled, Filter, Frame, Seek, Signal};
 
 const PLAY: usize = 0;
 const PAUSE: usize = 1;
@@ -9,13 +12,19 @@ const STOP: usize = 2;
 /// A source that can be paused or permanently stopped
 pub struct Stop<T: ?Sized> {
     state: AtomicUsize,
+    /// Identifies groups of sources affected by mixer-wide commands
+    tag: Option<u32>,
+    /// Fade out in progress, if any
+    fade: Cell<Option<Fade>>,
     inner: T,
 }
 
 impl<T> Stop<T> {
-    pub(crate) fn new(signal: T) -> Self {
+    pub(crate) fn new(signal: T, tag: Option<u32>) -> Self {
         Self {
             state: AtomicUsize::new(PLAY),
+            tag,
+            fade: Cell::new(None),
             inner: signal,
         }
     }
@@ -34,6 +43,59 @@ impl<T: ?Sized> Stop<T> {
     pub(crate) fn is_stopped(&self) -> bool {
         self.state.load(Ordering::Relaxed) == STOP
     }
+
+    /// Get a control for a source whose type has been erased
+    pub(crate) fn control(&self) -> StopControl<'_> {
+        StopControl(&self.state)
+    }
+}
+
+impl<T: ?Sized> Voice for Stop<T> {
+    fn apply(&self, command: &Command) {
+        if !command.voices.matches(self.tag) {
+            return;
+        }
+        match command.action {
+            Action::Pause => {
+                let _ =
+                    self.state
+                        .compare_exchange(PLAY, PAUSE, Ordering::Relaxed, Ordering::Relaxed);
+            }
+            Action::Resume => {
+                let _ =
+                    self.state
+                        .compare_exchange(PAUSE, PLAY, Ordering::Relaxed, Ordering::Relaxed);
+            }
+            Action::Stop => self.stop(),
+            Action::FadeOut(duration) => {
+                let gain = self.fade.get().map_or(1.0, |fade| fade.gain(0.0));
+                self.fade.set(Some(Fade {
+                    gain,
+                    duration,
+                    elapsed: 0.0,
+                }));
+            }
+        }
+    }
+}
+
+impl<T: Signal + ?Sized> Stop<T> {
+    /// Scale `out`, just sampled from this source, by the fade out in progress, if any
+    ///
+    /// Separate from [`Signal::sample`] so that sources are only required to have scalable frames
+    /// where they can be faded out.
+    pub(crate) fn fade(&self, interval: f32, out: &mut [T::Frame])
+    where
+        T::Frame: Frame,
+    {
+        if let Some(fade) = self.fade.get() {
+            // `sample` already advanced the fade past `out`
+            let start = -interval * out.len() as f32;
+            for (i, x) in out.iter_mut().enumerate() {
+                *x = frame::scale(x, fade.gain(start + i as f32 * interval));
+            }
+        }
+    }
 }
 
 impl<T: Signal + ?Sized> Signal for Stop<T> {
@@ -41,12 +103,26 @@ impl<T: Signal + ?Sized> Signal for Stop<T> {
 
     fn sample(&self, interval: f32, out: &mut [T::Frame]) {
         self.inner.sample(interval, out);
+        // The fade runs on the audio clock, independent of the source's playback position
+        if let Some(mut fade) = self.fade.get() {
+            fade.elapsed += interval * out.len() as f32;
+            self.fade.set(Some(fade));
+            if fade.is_silent() {
+                self.stop();
+            }
+        }
     }
 
     fn remaining(&self) -> f32 {
         let state = self.state.load(Ordering::Relaxed);
         match state {
-            PLAY => self.inner.remaining(),
+            PLAY => {
+                let remaining = self.inner.remaining();
+                match self.fade.get() {
+                    Some(fade) => remaining.min((fade.duration - fade.elapsed).max(0.0)),
+                    None => remaining,
+                }
+            }
             PAUSE => f32::INFINITY,
             _ => 0.0,
         }
@@ -109,3 +185,127 @@ impl<'a> StopControl<'a> {
         self.0.load(Ordering::Relaxed) == STOP
     }
 }
+
+/// Selects the sources affected by a mixer-wide command, e.g. [`MixerControl::pause`]
+///
+/// [`MixerControl::pause`]: crate::MixerControl::pause
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Voices {
+    /// Every source currently playing
+    All,
+    /// Only sources that were played with this tag
+    Tagged(u32),
+}
+
+impl Voices {
+    fn matches(self, tag: Option<u32>) -> bool {
+        match self {
+            Voices::All => true,
+            Voices::Tagged(x) => tag == Some(x),
+        }
+    }
+}
+
+/// An operation applied to many sources at once
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Command {
+    pub(crate) voices: Voices,
+    pub(crate) action: Action,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub(crate) enum Action {
+    Pause,
+    Resume,
+    Stop,
+    /// Fade to silence over this many seconds, then stop
+    FadeOut(f32),
+}
+
+/// A linear ramp to silence
+#[derive(Debug, Copy, Clone)]
+struct Fade {
+    /// Gain at the start of the fade
+    gain: f32,
+    /// Seconds until silence
+    duration: f32,
+    /// Seconds since the fade began
+    elapsed: f32,
+}
+
+impl Fade {
+    /// Gain `dt` seconds after the current time
+    fn gain(&self, dt: f32) -> f32 {
+        if self.duration <= 0.0 {
+            return 0.0;
+        }
+        let t = ((self.elapsed + dt) / self.duration).clamp(0.0, 1.0);
+        self.gain * (1.0 - t)
+    }
+
+    fn is_silent(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Constant;
+
+    #[test]
+    fn fade_out() {
+        let s = Stop::new(Constant::new(1.0), Some(1));
+        s.apply(&Command {
+            voices: Voices::Tagged(2),
+            action: Action::FadeOut(1.0),
+        });
+        assert_eq!(s.remaining(), f32::INFINITY);
+        s.apply(&Command {
+            voices: Voices::Tagged(1),
+            action: Action::FadeOut(1.0),
+        });
+        let mut buf = [0.0; 3];
+        s.sample(0.25, &mut buf);
+        s.fade(0.25, &mut buf);
+        assert_eq!(buf, [1.0, 0.75, 0.5]);
+        assert_eq!(s.remaining(), 0.25);
+        // Seeking doesn't affect the fade
+        s.seek(-0.25);
+        s.sample(0.25, &mut buf);
+        s.fade(0.25, &mut buf);
+        assert_eq!(buf, [0.25, 0.0, 0.0]);
+        assert!(s.is_stopped());
+        assert_eq!(s.remaining(), 0.0);
+
+        // Instantaneous fades silence the source rather than producing NaN
+        let s = Stop::new(Constant::new(1.0), None);
+        s.apply(&Command {
+            voices: Voices::All,
+            action: Action::FadeOut(0.0),
+        });
+        s.sample(0.25, &mut buf);
+        s.fade(0.25, &mut buf);
+        assert_eq!(buf, [0.0; 3]);
+        assert!(s.is_stopped());
+    }
+
+    #[test]
+    fn pause_resume() {
+        let s = Stop::new(Constant::new(1.0), None);
+        let pause = Command {
+            voices: Voices::All,
+            action: Action::Pause,
+        };
+        s.apply(&pause);
+        assert!(s.is_paused());
+        s.apply(&Command {
+            voices: Voices::All,
+            action: Action::Resume,
+        });
+        assert!(!s.is_paused());
+        s.stop();
+        s.apply(&pause);
+        assert!(s.is_stopped());
+    }
+}
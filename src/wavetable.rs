@@ -0,0 +1,239 @@
+use alloc::{boxed::Box, sync::Arc, vec};
+use core::{
+    cell::Cell,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use crate::{
+    fft::{Complex, Fft},
+    math::Float,
+    Controlled, Frames, Sample, Seek, Signal,
+};
+
+/// A [`Signal`] that plays single-cycle waveforms at a controllable frequency, forever
+///
+/// Unlike [`Cycle`](crate::Cycle), playback speed is independent of the sample rate of the source,
+/// and aliasing is avoided by playing copies of each table with high harmonics removed as the
+/// frequency rises. Multiple tables can be supplied, in which case the morph parameter crossfades
+/// between adjacent tables, allowing the timbre to evolve smoothly.
+pub struct Wavetable {
+    /// Band-limited copies of every table, indexed by level, then table, then sample
+    mips: Box<[Sample]>,
+    /// Length of each table, in samples
+    len: usize,
+    tables: usize,
+    levels: usize,
+    /// Fraction of a cycle, in [0, 1)
+    phase: Cell<f32>,
+    /// Cycles per second
+    frequency: AtomicU32,
+    /// Position between the first and last tables, in [0, 1]
+    morph: AtomicU32,
+    /// Morph position as of the end of the last call to `sample`
+    prev_morph: Cell<f32>,
+}
+
+impl Wavetable {
+    /// Construct an oscillator from `frames`, containing single-cycle tables `table_len` samples
+    /// long laid end-to-end, that cycles `frequency_hz` times per second
+    ///
+    /// The sample rate of `frames` is ignored. Band-limited copies of each table are computed
+    /// here, which is somewhat expensive, so avoid constructing wavetables on the audio thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `table_len` is not a power of two greater than 1, or `frames` is not a nonzero
+    /// multiple of `table_len` samples long.
+    // `usize::is_multiple_of` requires a newer compiler than this crate otherwise needs
+    #[allow(clippy::manual_is_multiple_of)]
+    pub fn new(frames: Arc<Frames<Sample>>, table_len: usize, frequency_hz: f32) -> Self {
+        assert!(
+            table_len.is_power_of_two() && table_len > 1,
+            "table length must be a power of two greater than 1"
+        );
+        assert!(
+            !frames.is_empty() && frames.len() % table_len == 0,
+            "frames must hold a whole number of tables"
+        );
+        let tables = frames.len() / table_len;
+        // Level `m` retains harmonics up to `table_len / 2^(m+1)`, down to a single harmonic
+        let levels = (table_len / 2).max(1).trailing_zeros() as usize + 1;
+
+        let fft = Fft::new(table_len);
+        let mut mips = vec![0.0; levels * tables * table_len].into_boxed_slice();
+        let mut spectrum = vec![Complex::ZERO; table_len];
+        let mut buffer = vec![Complex::ZERO; table_len];
+        for (table, samples) in frames.chunks(table_len).enumerate() {
+            for (x, &s) in spectrum.iter_mut().zip(samples) {
+                *x = Complex::new(s, 0.0);
+            }
+            fft.forward(&mut spectrum);
+            for level in 0..levels {
+                let harmonics = (table_len / 2) >> level;
+                buffer.copy_from_slice(&spectrum);
+                // Remove harmonics above the limit, along with their negative frequency mirrors
+                for (i, x) in buffer.iter_mut().enumerate() {
+                    if i.min(table_len - i) > harmonics {
+                        *x = Complex::ZERO;
+                    }
+                }
+                fft.inverse(&mut buffer);
+                let start = (level * tables + table) * table_len;
+                for (out, x) in mips[start..start + table_len].iter_mut().zip(&buffer) {
+                    *out = x.re / table_len as f32;
+                }
+            }
+        }
+
+        Self {
+            mips,
+            len: table_len,
+            tables,
+            levels,
+            phase: Cell::new(0.0),
+            frequency: AtomicU32::new(frequency_hz.to_bits()),
+            morph: AtomicU32::new(0.0f32.to_bits()),
+            prev_morph: Cell::new(0.0),
+        }
+    }
+
+    fn frequency(&self) -> f32 {
+        f32::from_bits(self.frequency.load(Ordering::Relaxed))
+    }
+
+    /// Linearly interpolate `table` at mip `level` at fraction of a cycle `phase`
+    fn get(&self, level: usize, table: usize, phase: f32) -> f32 {
+        let start = (level * self.tables + table) * self.len;
+        let table = &self.mips[start..start + self.len];
+        let s = phase * self.len as f32;
+        let a = (s as usize).min(self.len - 1);
+        let b = (a + 1) % self.len;
+        let fract = s - a as f32;
+        table[a] + (table[b] - table[a]) * fract
+    }
+}
+
+impl Signal for Wavetable {
+    type Frame = Sample;
+
+    fn sample(&self, interval: f32, out: &mut [Sample]) {
+        let dt = self.frequency() * interval;
+        // Select the most detailed level whose highest harmonic falls below the Nyquist frequency
+        let level = (self.len as f32 * dt.abs()).log2().ceil().max(0.0) as usize;
+        let level = level.min(self.levels - 1);
+
+        // Ramp the morph position over the block to avoid discontinuities
+        let morph = f32::from_bits(self.morph.load(Ordering::Relaxed)).clamp(0.0, 1.0);
+        let prev_morph = self.prev_morph.replace(morph);
+        let step = (morph - prev_morph) / out.len().max(1) as f32;
+
+        let last = (self.tables - 1) as f32;
+        let mut phase = self.phase.get();
+        for (i, x) in out.iter_mut().enumerate() {
+            let position = (prev_morph + step * (i + 1) as f32) * last;
+            let a = (position as usize).min(self.tables - 1);
+            let b = (a + 1).min(self.tables - 1);
+            let fract = position - a as f32;
+            let (a, b) = (self.get(level, a, phase), self.get(level, b, phase));
+            *x = a + (b - a) * fract;
+            phase = (phase + dt).rem_euclid(1.0);
+        }
+        self.phase.set(phase);
+    }
+}
+
+impl Seek for Wavetable {
+    fn seek(&self, seconds: f32) {
+        self.phase
+            .set((self.phase.get() + seconds * self.frequency()).rem_euclid(1.0));
+    }
+}
+
+/// Thread-safe control for a [`Wavetable`] signal
+pub struct WavetableControl<'a> {
+    frequency: &'a AtomicU32,
+    morph: &'a AtomicU32,
+}
+
+unsafe impl<'a> Controlled<'a> for Wavetable {
+    type Control = WavetableControl<'a>;
+
+    unsafe fn make_control(signal: &'a Wavetable) -> Self::Control {
+        WavetableControl {
+            frequency: &signal.frequency,
+            morph: &signal.morph,
+        }
+    }
+}
+
+impl<'a> WavetableControl<'a> {
+    /// Get the current frequency in Hz
+    pub fn frequency(&self) -> f32 {
+        f32::from_bits(self.frequency.load(Ordering::Relaxed))
+    }
+
+    /// Set the frequency in Hz
+    pub fn set_frequency(&mut self, frequency_hz: f32) {
+        self.frequency
+            .store(frequency_hz.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Get the current morph position
+    pub fn morph(&self) -> f32 {
+        f32::from_bits(self.morph.load(Ordering::Relaxed))
+    }
+
+    /// Set the morph position, where 0 plays the first table, 1 plays the last, and intermediate
+    /// values crossfade between adjacent tables
+    ///
+    /// Changes are smoothed over the next block of samples.
+    pub fn set_morph(&mut self, morph: f32) {
+        self.morph.store(morph.to_bits(), Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+    use core::f32::consts::TAU;
+
+    const LEN: usize = 64;
+
+    #[test]
+    fn band_limited() {
+        // A square wave at a frequency where only the fundamental falls below Nyquist
+        let square = (0..LEN)
+            .map(|i| if i < LEN / 2 { 1.0 } else { -1.0 })
+            .collect::<Vec<_>>();
+        let (mut re, mut im) = (0.0, 0.0);
+        for (i, x) in square.iter().enumerate() {
+            let angle = TAU * i as f32 / LEN as f32;
+            re += x * angle.cos();
+            im -= x * angle.sin();
+        }
+        let fundamental = |t: f32| 2.0 / LEN as f32 * (re * (TAU * t).cos() - im * (TAU * t).sin());
+
+        let dt = 0.3;
+        let s = Wavetable::new(Frames::from_slice(1, &square), LEN, dt);
+        let mut buf = [0.0; 32];
+        s.sample(1.0, &mut buf);
+        for (i, x) in buf.iter().enumerate() {
+            let expected = fundamental((i as f32 * dt) % 1.0);
+            assert!((x - expected).abs() < 5e-3, "{} vs {}", x, expected);
+        }
+    }
+
+    #[test]
+    fn morph() {
+        let tables = (0..3 * LEN).map(|i| (i / LEN) as f32).collect::<Vec<_>>();
+        let s = Wavetable::new(Frames::from_slice(1, &tables), LEN, 100.0);
+        let mut control = unsafe { Wavetable::make_control(&s) };
+        control.set_morph(0.75);
+        let mut buf = [0.0; 4];
+        s.sample(0.001, &mut buf);
+        assert_eq!(buf, [0.375, 0.75, 1.125, 1.5]);
+        s.sample(0.001, &mut buf);
+        assert_eq!(buf, [1.5; 4]);
+    }
+}
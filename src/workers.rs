@@ -0,0 +1,276 @@
+//! Parallel rendering support
+
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::{
+    cell::UnsafeCell,
+    hint, mem,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+use std::{
+    panic::{self, AssertUnwindSafe},
+    thread::{self, JoinHandle},
+};
+
+use crate::{frame, Frame};
+
+/// Threads that help the audio thread render independent signals
+///
+/// The audio thread participates in every job, then busy-waits for the workers to finish their
+/// share. Waiting is therefore bounded by the time it takes the slowest worker to render its
+/// signals, provided that worker threads are not preempted; for best results, give them the same
+/// real-time priority as the audio thread. Workers that have gone idle for long enough to sleep
+/// must be woken by a system call. This is an exception to the rule that
+/// [`Signal::sample`](crate::Signal::sample) must not wait, which callers must opt into.
+pub(crate) struct Workers {
+    shared: Arc<Shared>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl Workers {
+    /// Spawn `count` worker threads
+    pub(crate) fn new(count: usize) -> Self {
+        let shared = Arc::new(Shared {
+            job: UnsafeCell::new(None),
+            generation: AtomicUsize::new(0),
+            pending: AtomicUsize::new(0),
+            sleeping: (0..count).map(|_| AtomicBool::new(false)).collect(),
+            panicked: AtomicBool::new(false),
+            shutdown: AtomicBool::new(false),
+        });
+        let threads = (1..=count)
+            .map(|participant| {
+                let shared = shared.clone();
+                thread::Builder::new()
+                    .name("oddio worker".into())
+                    .spawn(move || shared.work(participant))
+                    .expect("failed to spawn worker thread")
+            })
+            .collect();
+        Self { shared, threads }
+    }
+
+    /// Number of threads that run each job, including the caller
+    pub(crate) fn participants(&self) -> usize {
+        self.threads.len() + 1
+    }
+
+    /// Invoke `job` once for each participant index, on distinct threads, returning once all have
+    /// completed
+    ///
+    /// # Panics
+    ///
+    /// Panics if `job` panics on any thread. Even then, no worker is still running `job` once this
+    /// returns or unwinds.
+    pub(crate) fn run(&self, job: &(dyn Fn(usize) + Sync)) {
+        self.shared
+            .pending
+            .store(self.threads.len(), Ordering::Relaxed);
+        unsafe {
+            // Sound because `wait` ensures we don't return, even by unwinding, until every worker
+            // is done with `job`
+            *self.shared.job.get() = Some(mem::transmute::<
+                *const (dyn Fn(usize) + Sync + '_),
+                *const (dyn Fn(usize) + Sync + 'static),
+            >(job));
+        }
+        self.shared.generation.fetch_add(1, Ordering::SeqCst);
+        let wait = Wait(&self.shared.pending);
+        for (thread, sleeping) in self.threads.iter().zip(self.shared.sleeping.iter()) {
+            // Waking a thread is a system call, so skip it for workers that are still spinning
+            if sleeping.load(Ordering::SeqCst) {
+                thread.thread().unpark();
+            }
+        }
+        job(0);
+        drop(wait);
+        if self.shared.panicked.swap(false, Ordering::Relaxed) {
+            panic!("worker thread panicked");
+        }
+    }
+}
+
+/// Waits for all workers to finish the current job when dropped, including during unwinding
+struct Wait<'a>(&'a AtomicUsize);
+
+impl Drop for Wait<'_> {
+    fn drop(&mut self) {
+        while self.0.load(Ordering::Acquire) != 0 {
+            hint::spin_loop();
+        }
+    }
+}
+
+impl Drop for Workers {
+    fn drop(&mut self) {
+        self.shared.shutdown.store(true, Ordering::Relaxed);
+        self.shared.generation.fetch_add(1, Ordering::SeqCst);
+        for thread in self.threads.drain(..) {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+struct Shared {
+    job: UnsafeCell<Option<*const (dyn Fn(usize) + Sync)>>,
+    /// Incremented whenever a new job is posted
+    generation: AtomicUsize,
+    /// Number of workers that have yet to finish the current job
+    pending: AtomicUsize,
+    /// Whether each worker may be parked, and hence must be unparked to see a new job
+    sleeping: Box<[AtomicBool]>,
+    /// Whether a worker panicked while running the current job
+    panicked: AtomicBool,
+    shutdown: AtomicBool,
+}
+
+// Sound because `job` is only written while no workers are reading it
+unsafe impl Send for Shared {}
+unsafe impl Sync for Shared {}
+
+impl Shared {
+    fn work(&self, participant: usize) {
+        let sleeping = &self.sleeping[participant - 1];
+        let mut seen = 0;
+        let mut idle = 0;
+        loop {
+            let generation = self.generation.load(Ordering::Acquire);
+            if generation == seen {
+                // Spin briefly to reduce wakeup latency when jobs are posted back-to-back
+                if idle < SPIN_LIMIT {
+                    idle += 1;
+                    hint::spin_loop();
+                } else {
+                    // Announce that we're going to sleep before checking for work one last time,
+                    // so that a job posted concurrently either is seen here or unparks us
+                    sleeping.store(true, Ordering::SeqCst);
+                    if self.generation.load(Ordering::SeqCst) == seen {
+                        thread::park();
+                    }
+                    sleeping.store(false, Ordering::Relaxed);
+                }
+                continue;
+            }
+            seen = generation;
+            idle = 0;
+            if self.shutdown.load(Ordering::Relaxed) {
+                return;
+            }
+            let result = panic::catch_unwind(AssertUnwindSafe(|| unsafe {
+                let job = (*self.job.get()).expect("job posted");
+                (*job)(participant);
+            }));
+            if result.is_err() {
+                self.panicked.store(true, Ordering::Relaxed);
+            }
+            // Always reached, so the audio thread never waits forever
+            self.pending.fetch_sub(1, Ordering::Release);
+        }
+    }
+}
+
+/// Number of times an idle worker checks for work before sleeping
+const SPIN_LIMIT: u32 = 1 << 12;
+
+/// Workers along with a private output buffer for each participant
+pub(crate) struct Parallel<T> {
+    workers: Workers,
+    buffers: Box<[Buffer<T>]>,
+}
+
+impl<T: Frame> Parallel<T> {
+    /// Spawn `workers` threads that mix up to `capacity` frames at a time
+    pub(crate) fn new(workers: usize, capacity: usize) -> Self
+    where
+        T: Clone + Send,
+    {
+        let workers = Workers::new(workers);
+        let buffers = (0..workers.participants())
+            .map(|_| Buffer(UnsafeCell::new(alloc::vec![T::ZERO; capacity].into())))
+            .collect();
+        Self { workers, buffers }
+    }
+
+    /// Maximum number of frames that can be mixed by one call to [`mix`](Self::mix)
+    pub(crate) fn capacity(&self) -> usize {
+        unsafe { (&*self.buffers[0].0.get()).len() }
+    }
+
+    /// Call `render(participant, participants, buffer)` for every participant with a zeroed buffer
+    /// of `out.len()` frames, then sum the buffers into `out` in participant order
+    ///
+    /// Summation order is independent of scheduling, so output is deterministic.
+    pub(crate) fn mix(&mut self, out: &mut [T], render: impl Fn(usize, usize, &mut [T]) + Sync) {
+        let n = out.len();
+        debug_assert!(n <= self.capacity());
+        let participants = self.workers.participants();
+        let buffers = &self.buffers;
+        self.workers.run(&|participant| {
+            // Sound because each participant index is passed to exactly one thread
+            let buffer = unsafe { &mut (&mut *buffers[participant].0.get())[..n] };
+            for x in buffer.iter_mut() {
+                *x = T::ZERO;
+            }
+            render(participant, participants, buffer);
+        });
+        for (i, o) in out.iter_mut().enumerate() {
+            *o = T::ZERO;
+            for buffer in self.buffers.iter_mut() {
+                *o = frame::mix(o, &buffer.0.get_mut()[i]);
+            }
+        }
+    }
+}
+
+struct Buffer<T>(UnsafeCell<Box<[T]>>);
+
+// Sound because each buffer is only accessed by one participant at a time, and `Parallel::new`
+// requires `T: Send`
+unsafe impl<T> Sync for Buffer<T> {}
+
+/// Wrapper asserting that it's safe to share a reference to `T` with other participants
+///
+/// Used to expose signals, which are generally `!Sync`, to workers which access disjoint subsets of
+/// them.
+pub(crate) struct AssertSync<T>(pub(crate) T);
+
+unsafe impl<T> Sync for AssertSync<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mix() {
+        let mut parallel = Parallel::<f32>::new(3, 8);
+        let mut out = [0.0; 4];
+        for _ in 0..16 {
+            parallel.mix(&mut out, |participant, participants, buf| {
+                assert_eq!(participants, 4);
+                for x in buf {
+                    *x = participant as f32;
+                }
+            });
+            assert_eq!(out, [6.0; 4]);
+        }
+    }
+
+    #[test]
+    fn panic() {
+        let workers = Workers::new(2);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            workers.run(&|participant| {
+                if participant == 1 {
+                    panic!("test");
+                }
+            })
+        }));
+        assert!(result.is_err());
+        // Workers survive, and later jobs run normally
+        let count = AtomicUsize::new(0);
+        workers.run(&|_| {
+            count.fetch_add(1, Ordering::Relaxed);
+        });
+        assert_eq!(count.load(Ordering::Relaxed), 3);
+    }
+}